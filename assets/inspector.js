@@ -0,0 +1,74 @@
+const table = document.getElementById("packets");
+const body = table.querySelector("tbody");
+const id = Number(table.dataset.id);
+const sessionFilter = document.getElementById("session-filter");
+const typeFilter = document.getElementById("type-filter");
+const maxRows = 200;
+let lastSeq = 0;
+
+function matchesFilters(row) {
+    const session = sessionFilter.value.trim();
+    const type = typeFilter.value;
+    return (session === "" || row.dataset.session === session)
+        && (type === "" || row.dataset.type === type);
+}
+
+function applyFilters() {
+    for (const row of body.rows) {
+        row.hidden = !matchesFilters(row);
+    }
+}
+
+function routeCell(packet) {
+    const cell = document.createElement("td");
+    packet.hops.forEach((hop, index) => {
+        if (index > 0) {
+            cell.append(" → ");
+        }
+        const span = cell.appendChild(document.createElement("span"));
+        span.textContent = hop.toString();
+        if (index === packet.hop_index) {
+            span.classList.add("current-hop");
+        }
+    });
+    return cell;
+}
+
+function addRow(packet) {
+    const row = document.createElement("tr");
+    row.dataset.session = packet.session_id.toString();
+    row.dataset.type = packet.packet_type;
+    for (const value of [packet.seq, packet.packet_type, packet.session_id, packet.fragment_index ?? "-"]) {
+        row.appendChild(document.createElement("td")).textContent = value.toString();
+    }
+    row.appendChild(routeCell(packet));
+    const decision = row.appendChild(document.createElement("td"));
+    decision.textContent = packet.decision;
+    decision.classList.add("decision", packet.decision.split("(")[0].toLowerCase());
+    row.hidden = !matchesFilters(row);
+    body.prepend(row);
+
+    while (body.rows.length > maxRows) {
+        body.deleteRow(-1);
+    }
+}
+
+async function poll() {
+    try {
+        const response = await fetch(`/api/drones/${id}/packets?after=${lastSeq}`);
+        if (response.ok) {
+            for (const packet of await response.json()) {
+                addRow(packet);
+                lastSeq = packet.seq;
+            }
+        }
+    } catch (error) {
+        console.log(error);
+    }
+}
+
+sessionFilter.addEventListener("input", applyFilters);
+typeFilter.addEventListener("change", applyFilters);
+
+poll();
+setInterval(poll, 500);
@@ -0,0 +1,14 @@
+let assetsVersion = null;
+
+setInterval(async () => {
+    try {
+        const response = await fetch("/assets-version");
+        const version = await response.text();
+        if (assetsVersion !== null && version !== assetsVersion) {
+            location.reload();
+        }
+        assetsVersion = version;
+    } catch (error) {
+        console.log("Could not check the assets:", error);
+    }
+}, 1000);
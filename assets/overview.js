@@ -0,0 +1,94 @@
+const tiles = document.getElementById("tiles");
+const sortSelect = document.getElementById("sort");
+const animationTime = 1500;
+const styleSheet = document.styleSheets[0];
+styleSheet.insertRule(`.field-element { animation-duration: ${animationTime}ms; }`, styleSheet.cssRules.length);
+
+const drones = new Map();
+
+function droneTile(id) {
+    if (!drones.has(id)) {
+        const tile = tiles.appendChild(document.createElement("a"));
+        tile.classList.add("tile");
+        tile.href = `/${id}`;
+        const title = tile.appendChild(document.createElement("h2"));
+        title.textContent = `Bagel Bomber ${id}`;
+        const field = tile.appendChild(document.createElement("div"));
+        field.classList.add("playback-field");
+        const counters = tile.appendChild(document.createElement("p"));
+        counters.classList.add("tile-counters");
+        drones.set(id, {tile, field, counters, pdr: 0, forwarded: 0, exploded: 0, dropRate: 0});
+    }
+    return drones.get(id);
+}
+
+function dropBagel(field, exploded) {
+    const element = field.appendChild(document.createElement("div"));
+    element.classList.add("field-element", "drop");
+    element.innerHTML = exploded ? "💥" : "🥯";
+    element.style.setProperty("--field-position", Math.random().toString());
+    setTimeout(() => {
+        element.remove();
+    }, animationTime);
+}
+
+function updateTile(update) {
+    const drone = droneTile(update.id);
+    drone.pdr = update.pdr ?? drone.pdr;
+    drone.forwarded = update.forwarded ?? drone.forwarded;
+    drone.exploded = update.exploded ?? drone.exploded;
+    const total = drone.forwarded + drone.exploded;
+    drone.dropRate = total > 0 ? drone.exploded / total : 0;
+    drone.counters.textContent =
+        `PDR ${drone.pdr} · ${drone.forwarded} forwarded · ${drone.exploded} exploded · ${(drone.dropRate * 100).toFixed(1)}% dropped`;
+    // a busy drone would bury its tile in bagels, a few are enough to show the rhythm
+    for (const drop of (update.drops ?? []).slice(-10)) {
+        dropBagel(drone.field, drop.exploded);
+    }
+}
+
+function sortTiles() {
+    const sorted = [...drones.entries()];
+    if (sortSelect.value === "drop-rate") {
+        sorted.sort(([, a], [, b]) => b.dropRate - a.dropRate);
+    } else {
+        sorted.sort(([a], [b]) => a - b);
+    }
+    // moving a tile restarts its animations, so only the ones out of place are moved
+    sorted.forEach(([, drone], index) => {
+        const current = tiles.children[index];
+        if (current !== drone.tile) {
+            tiles.insertBefore(drone.tile, current ?? null);
+        }
+    });
+}
+
+sortSelect.addEventListener("change", sortTiles);
+
+const hostName = window.location.hostname;
+const ws = new WebSocket(`ws://${hostName}:${tiles.dataset.webSocketPort}`);
+
+ws.onopen = () => {
+    ws.send(JSON.stringify({type: "subscribe", drones: "all", events: ["drops", "pdr", "counters"]}));
+};
+
+ws.onmessage = (event) => {
+    const message = JSON.parse(event.data);
+    switch (message.type) {
+        case "update":
+            message.drones.forEach(updateTile);
+            sortTiles();
+            break;
+        case "removed":
+            drones.get(message.id)?.tile.remove();
+            drones.delete(message.id);
+            break;
+        case "error":
+            console.log("WebSocket error:", message.message);
+            break;
+    }
+};
+
+ws.onclose = () => {
+    console.log("WebSocket connection closed.");
+};
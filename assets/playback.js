@@ -0,0 +1,124 @@
+const tiles = document.getElementById("tiles");
+const timeline = document.getElementById("timeline");
+const timeLabel = document.getElementById("time");
+const playButton = document.getElementById("play");
+const speedSelect = document.getElementById("speed");
+const animationTime = 1500;
+const tickTime = 50;
+const styleSheet = document.styleSheets[0];
+styleSheet.insertRule(`.field-element { animation-duration: ${animationTime}ms; }`, styleSheet.cssRules.length);
+
+let recording = [];
+let time = 0;
+let playing = false;
+const drones = new Map();
+
+function droneTile(id) {
+    if (!drones.has(id)) {
+        const tile = tiles.appendChild(document.createElement("div"));
+        tile.classList.add("tile");
+        const title = tile.appendChild(document.createElement("h2"));
+        const field = tile.appendChild(document.createElement("div"));
+        field.classList.add("playback-field");
+        drones.set(id, {tile, title, field, pdr: 0});
+    }
+    return drones.get(id);
+}
+
+function updateTitle(drone, id) {
+    drone.title.textContent = `Bagel Bomber ${id} · PDR ${drone.pdr}`;
+}
+
+function applyEvent(event, animate) {
+    switch (event.type) {
+        case "DroneAdded":
+        case "PDRChanged": {
+            const drone = droneTile(event.id);
+            drone.pdr = event.pdr;
+            drone.tile.hidden = false;
+            updateTitle(drone, event.id);
+            break;
+        }
+        case "DroneRemoved":
+            droneTile(event.id).tile.hidden = true;
+            break;
+        case "BagelDropped":
+            if (animate) {
+                dropBagel(droneTile(event.id).field, event.exploded);
+            }
+            break;
+    }
+}
+
+function dropBagel(field, exploded) {
+    const element = field.appendChild(document.createElement("div"));
+    element.classList.add("field-element", "drop");
+    element.innerHTML = exploded ? "💥" : "🥯";
+    element.style.setProperty("--field-position", Math.random().toString());
+    setTimeout(() => {
+        element.remove();
+    }, animationTime);
+}
+
+/// Rebuilds the state of every drone at the given time, without animations
+function seek(target) {
+    for (const drone of drones.values()) {
+        drone.tile.hidden = true;
+        drone.field.replaceChildren();
+    }
+    for (const message of recording) {
+        if (message.time > target) {
+            break;
+        }
+        applyEvent(message.event, false);
+    }
+    time = target;
+    showTime();
+}
+
+function showTime() {
+    timeline.value = time.toString();
+    timeLabel.textContent = `${time.toFixed(1)}s`;
+}
+
+function tick() {
+    if (!playing) {
+        return;
+    }
+    const next = Math.min(time + tickTime / 1000 * Number(speedSelect.value), Number(timeline.max));
+    for (const message of recording) {
+        if (message.time > time && message.time <= next) {
+            applyEvent(message.event, true);
+        }
+    }
+    time = next;
+    showTime();
+    if (time >= Number(timeline.max)) {
+        setPlaying(false);
+    }
+}
+
+function setPlaying(value) {
+    playing = value;
+    playButton.textContent = playing ? "Pause" : "Play";
+}
+
+playButton.addEventListener("click", () => {
+    if (!playing && time >= Number(timeline.max)) {
+        seek(0);
+    }
+    setPlaying(!playing);
+});
+
+timeline.addEventListener("input", () => {
+    seek(Number(timeline.value));
+});
+
+fetch("/recording")
+    .then(response => response.json())
+    .then(messages => {
+        recording = messages;
+        timeline.max = (recording.length > 0 ? recording[recording.length - 1].time : 0).toString();
+        seek(0);
+        setInterval(tick, tickTime);
+    });
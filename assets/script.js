@@ -2,6 +2,7 @@ const field = document.getElementById("field");
 const id = Number(field.dataset.id);
 let pdr = Number(field.dataset.pdr);
 field.removeAttribute("data-pdr");
+let explosionStyle = field.dataset.explosionStyle;
 const dropQueue = [];
 const animationTime = 1500;
 const styleSheet = document.styleSheets[0];
@@ -22,10 +23,44 @@ function addElementToField(emoji, isSmall = false) {
     return fieldElement;
 }
 
+function addParticles(element, emojis, count, distance) {
+    for (let i = 0; i < count; i++) {
+        const particle = element.appendChild(document.createElement("span"));
+        particle.classList.add("particle");
+        particle.innerHTML = emojis[i % emojis.length];
+        const angle = 2 * Math.PI * i / count;
+        particle.style.setProperty("--dx", `${Math.cos(angle) * distance}px`);
+        particle.style.setProperty("--dy", `${Math.sin(angle) * distance}px`);
+    }
+}
+
+const explosions = {
+    "classic": () => {
+        addElementToField("💥").classList.add("drop");
+    },
+    "confetti-flour-burst": () => {
+        const element = addElementToField("🎉");
+        element.classList.add("drop");
+        addParticles(element, ["🎊", "✨", "☁️"], 9, 40);
+    },
+    "bagel-pocalypse": () => {
+        const element = addElementToField("🔥");
+        element.classList.add("drop", "apocalyptic");
+        addParticles(element, ["🥯", "💥"], 6, 70);
+        field.classList.remove("shake");
+        void field.offsetWidth;
+        field.classList.add("shake");
+    },
+};
+
 const dropIntervalId = setInterval(() => {
     if (dropQueue.length > 0) {
         const drop = dropQueue.shift();
-        addElementToField(drop.exploded ? "💥" : "🥯").classList.add("drop");
+        if (drop.exploded) {
+            (explosions[explosionStyle] ?? explosions["classic"])();
+        } else {
+            addElementToField("🥯").classList.add("drop");
+        }
     }
 }, 200);
 
@@ -95,19 +130,128 @@ dynamicInterval(() => {
     addEnvironmentEmoji();
 }, () => Math.random() * maxEnvironmentSpawnDelay * (1 - pdr) + 100);
 
+const pdrValue = document.getElementById("pdr-value");
+const tokenInput = document.getElementById("token");
+const controlStatus = document.getElementById("control-status");
+tokenInput.value = localStorage.getItem("bagel-token") ?? "";
+tokenInput.addEventListener("change", () => {
+    localStorage.setItem("bagel-token", tokenInput.value);
+});
+
+async function sendControl(action, body = "") {
+    try {
+        const response = await fetch(`/${id}/${action}`, {
+            method: "POST",
+            headers: {"Authorization": `Bearer ${tokenInput.value}`},
+            body,
+        });
+        controlStatus.textContent = await response.text();
+    } catch (error) {
+        controlStatus.textContent = error.toString();
+    }
+}
+
+document.getElementById("set-pdr").addEventListener("click", () => {
+    sendControl("pdr", document.getElementById("pdr-input").value);
+});
+
+document.getElementById("restore-neighbor").addEventListener("click", () => {
+    sendControl("restore-neighbor", document.getElementById("neighbor-input").value);
+});
+
+document.getElementById("remove-neighbor").addEventListener("click", () => {
+    sendControl("remove-neighbor", document.getElementById("neighbor-input").value);
+});
+
+const explosionStyleInput = document.getElementById("explosion-style-input");
+explosionStyleInput.value = explosionStyle;
+
+document.getElementById("set-explosion-style").addEventListener("click", () => {
+    sendControl("explosion-style", explosionStyleInput.value);
+});
+
+document.getElementById("crash").addEventListener("click", () => {
+    if (confirm(`Crash Bagel Bomber ${id}?`)) {
+        sendControl("crash");
+    }
+});
+
+const chart = document.getElementById("chart");
+const chartContext = chart.getContext("2d");
+const rollingWindow = 30;
+
+function drawChart(buckets) {
+    const {width, height} = chart;
+    chartContext.clearRect(0, 0, width, height);
+    if (buckets.length === 0) {
+        return;
+    }
+
+    const start = buckets[0].time;
+    const span = Math.max(buckets[buckets.length - 1].time - start, 1);
+    const x = (time) => (time - start) / span * width;
+    const y = (rate) => height - rate * height;
+
+    chartContext.fillStyle = "#fff4";
+    for (const bucket of buckets) {
+        const total = bucket.forwarded + bucket.exploded;
+        chartContext.fillRect(x(bucket.time) - 1, y(bucket.exploded / total) - 1, 3, 3);
+    }
+
+    chartContext.lineWidth = 2;
+    chartContext.strokeStyle = "#f84";
+    chartContext.beginPath();
+    buckets.forEach((bucket, index) => {
+        const recent = buckets.slice(Math.max(0, index - rollingWindow + 1), index + 1);
+        const exploded = recent.reduce((sum, bucket) => sum + bucket.exploded, 0);
+        const total = recent.reduce((sum, bucket) => sum + bucket.forwarded + bucket.exploded, 0);
+        chartContext.lineTo(x(bucket.time), y(exploded / total));
+    });
+    chartContext.stroke();
+
+    chartContext.strokeStyle = "#8cf";
+    chartContext.beginPath();
+    buckets.forEach((bucket) => {
+        chartContext.lineTo(x(bucket.time), y(bucket.pdr));
+    });
+    chartContext.stroke();
+}
+
+async function updateChart() {
+    try {
+        const response = await fetch(`/api/drones/${id}/history`);
+        if (response.ok) {
+            drawChart(await response.json());
+        }
+    } catch (error) {
+        console.log(error);
+    }
+}
+
+updateChart();
+const chartIntervalId = setInterval(updateChart, 2000);
+
 const hostName = window.location.hostname;
-const ws = new WebSocket(`ws://${hostName}:8464`);
+const ws = new WebSocket(`ws://${hostName}:${field.dataset.webSocketPort}`);
 
 ws.onopen = () => {
     console.log("WebSocket connection established.");
-    ws.send(id.toString());
+    ws.send(JSON.stringify({type: "subscribe", drones: [id], events: ["drops", "pdr", "explosion_style"]}));
 };
 
 ws.onmessage = (event) => {
-    const data = JSON.parse(event.data);
-    pdr = data.pdr;
-    const drops = data.drops;
-    dropQueue.push(...drops);
+    const message = JSON.parse(event.data);
+    if (message.type !== "update") {
+        return;
+    }
+    for (const drone of message.drones) {
+        if (drone.pdr !== undefined) {
+            pdr = drone.pdr;
+            pdrValue.textContent = pdr.toString();
+        }
+        explosionStyle = drone.explosion_style ?? explosionStyle;
+        dropQueue.push(...(drone.drops ?? []));
+    }
 };
 
 ws.onerror = () => {
@@ -116,5 +260,6 @@ ws.onerror = () => {
 
 ws.onclose = () => {
     clearInterval(dropIntervalId);
+    clearInterval(chartIntervalId);
     console.log("WebSocket connection closed.");
 };
\ No newline at end of file
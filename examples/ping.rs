@@ -4,10 +4,17 @@ use std::time::Duration;
 use crossbeam_channel::{unbounded, Receiver, Sender};
 use drone_tester::{create_test_environment, DummyNode, PDRPolicy, Runnable, TestNodeInstructions};
 use wg_2024::controller::DroneCommand;
-use wg_2024::drone::Drone;
 use wg_2024::network::{NodeId, SourceRoutingHeader};
 use wg_2024::packet::{Fragment, Packet, PacketType};
 use bagel_bomber::BagelBomber;
+#[cfg(feature = "tui")]
+use bagel_bomber::TuiDashboard;
+#[cfg(feature = "tui")]
+use std::sync::Mutex;
+
+/// Run with `--features tui` to watch the drones in the terminal instead of the browser
+#[cfg(feature = "tui")]
+static DASHBOARD: Mutex<Option<TuiDashboard>> = Mutex::new(None);
 
 pub fn create_bagel_bomber(
     id: NodeId,
@@ -16,19 +23,31 @@ pub fn create_bagel_bomber(
     packet_send: HashMap<NodeId, Sender<Packet>>,
     pdr: f32,
 ) -> Box<dyn Runnable> {
-    Box::new(BagelBomber::new(
+    let builder = BagelBomber::builder(
         id,
         unbounded().0,
         controller_recv,
         packet_recv,
         packet_send,
         pdr,
-    ))
+    );
+    #[cfg(feature = "tui")]
+    let builder = match DASHBOARD.lock().unwrap().as_ref() {
+        Some(dashboard) => builder.gui(dashboard.handle()),
+        None => builder,
+    };
+    Box::new(builder.build())
 }
 
 fn main() {
     let ping_count = 3600;
 
+    #[cfg(feature = "tui")]
+    match TuiDashboard::start() {
+        Ok(dashboard) => *DASHBOARD.lock().unwrap() = Some(dashboard),
+        Err(err) => eprintln!("Could not start the terminal dashboard: {}", err),
+    }
+
     let client = TestNodeInstructions::with_node_id(40, vec![3], move |id: NodeId, packet_recv: Receiver<Packet>, packet_send: HashMap<NodeId, Sender<Packet>>| {
         println!("Client running");
 
@@ -92,5 +111,10 @@ fn main() {
         create_bagel_bomber,
         DummyNode::create_client_server,
         DummyNode::create_client_server,
-    )
+    );
+
+    #[cfg(feature = "tui")]
+    if let Some(dashboard) = DASHBOARD.lock().unwrap().take() {
+        dashboard.shutdown().ok();
+    }
 }
\ No newline at end of file
@@ -0,0 +1,100 @@
+//! Feeds a drone with packets and commands decoded from the fuzzer's bytes.
+//!
+//! `cargo +nightly fuzz run packet_pipeline` from the repository root. Panics, stack overflows
+//! from handlers calling each other and drones sending more than a packet can explain all end
+//! the run with the offending input in `fuzz/artifacts/`.
+
+#![no_main]
+
+use bagel_bomber::BagelBomber;
+use bincode::Options;
+use crossbeam_channel::{unbounded, Receiver};
+use libfuzzer_sys::fuzz_target;
+use serde::Deserialize;
+use std::collections::HashMap;
+use wg_2024::controller::{DroneCommand, DroneEvent};
+use wg_2024::drone::Drone;
+use wg_2024::network::NodeId;
+use wg_2024::packet::Packet;
+
+const DRONE_ID: NodeId = 1;
+const NEIGHBORS: [NodeId; 4] = [2, 3, 4, 5];
+/// Most bytes a single input may be decoded from
+const INPUT_LIMIT: u64 = 4096;
+
+/// A [`DroneCommand`] can't be deserialized because of its sender, so neighbors are picked
+/// by index among [`NEIGHBORS`]
+#[derive(Debug, Deserialize)]
+enum Input {
+    Packet(Packet),
+    AddSender(u8),
+    RemoveSender(u8),
+    /// Rounded to 0 or 1, so that the same input always drops the same packets
+    SetPacketDropRate(f32),
+    Crash,
+}
+
+fuzz_target!(|data: &[u8]| {
+    let options = bincode::DefaultOptions::new().with_limit(INPUT_LIMIT);
+    let mut bytes = data;
+    let mut inputs = Vec::new();
+    while let Ok(input) = options.deserialize_from::<_, Input>(&mut bytes) {
+        inputs.push(input);
+    }
+
+    let (events_send, events) = unbounded();
+    let mut senders = HashMap::new();
+    let mut receivers = Vec::new();
+    for id in NEIGHBORS {
+        let (sender, receiver) = unbounded();
+        senders.insert(id, sender);
+        receivers.push(receiver);
+    }
+    // nobody else holds the drone's sender, so a crash doesn't wait for more packets
+    let packet_recv = unbounded().1;
+    let mut drone = BagelBomber::new(
+        DRONE_ID,
+        events_send,
+        unbounded().1,
+        packet_recv,
+        senders.clone(),
+        0.0,
+    );
+
+    for input in inputs {
+        match input {
+            Input::Packet(packet) => drone.fuzz_packet(packet),
+            Input::AddSender(index) => {
+                let id = NEIGHBORS[index as usize % NEIGHBORS.len()];
+                drone.fuzz_command(DroneCommand::AddSender(id, senders[&id].clone()));
+            }
+            Input::RemoveSender(index) => {
+                let id = NEIGHBORS[index as usize % NEIGHBORS.len()];
+                drone.fuzz_command(DroneCommand::RemoveSender(id));
+            }
+            Input::SetPacketDropRate(pdr) => {
+                let pdr = if pdr >= 0.5 { 1.0 } else { 0.0 };
+                drone.fuzz_command(DroneCommand::SetPacketDropRate(pdr));
+            }
+            Input::Crash => drone.fuzz_command(DroneCommand::Crash),
+        }
+
+        // a packet is forwarded at most once per neighbor, when it's a flood, and a nack
+        // for it adds a dropped event
+        let sent = drain(&receivers);
+        let reported = events.try_iter().collect::<Vec<DroneEvent>>().len();
+        assert!(sent <= NEIGHBORS.len(), "{} packets sent for one input", sent);
+        assert!(
+            reported <= NEIGHBORS.len() + 2,
+            "{} events for one input",
+            reported
+        );
+    }
+});
+
+fn drain(receivers: &[Receiver<Packet>]) -> usize {
+    receivers
+        .iter()
+        .map(|receiver| receiver.try_iter().count())
+        .sum()
+}
@@ -1,3 +1,4 @@
+#[cfg(any(feature = "gui", feature = "metrics"))]
 use super::drone_gui;
 use crate::coin_toss;
 use crossbeam_channel::{select_biased, unbounded, Receiver, Sender};
@@ -7,7 +8,12 @@ use wg_2024::controller::*;
 use wg_2024::drone::*;
 use wg_2024::network::{NodeId, SourceRoutingHeader};
 use wg_2024::packet::{FloodRequest, Nack, NackType, NodeType, Packet, PacketType};
-use crate::drone_gui::GUIMessage;
+#[cfg(any(feature = "gui", feature = "metrics"))]
+use crate::clock::{self, Clock};
+#[cfg(any(feature = "gui", feature = "metrics"))]
+use crate::drone_gui::{ExplosionStyle, GuiHandle};
+#[cfg(any(feature = "gui", feature = "metrics"))]
+use std::sync::Arc;
 
 enum PacketHandler<'a> {
     Forward(&'a Sender<Packet>),
@@ -17,6 +23,31 @@ enum PacketHandler<'a> {
     SendToController,
 }
 
+#[cfg(any(feature = "gui", feature = "metrics"))]
+impl PacketHandler<'_> {
+    fn describe(&self) -> String {
+        match self {
+            PacketHandler::Forward(_) => "Forward".to_string(),
+            PacketHandler::Nack(nack) => format!("Nack({:?})", nack),
+            PacketHandler::FloodRequest => "FloodRequest".to_string(),
+            PacketHandler::Ignore => "Ignore".to_string(),
+            PacketHandler::SendToController => "SendToController".to_string(),
+        }
+    }
+}
+
+/// What the GUI asks of a drone through the drone's own channel, empty without a GUI
+pub enum InternalCommand {
+    #[cfg(any(feature = "gui", feature = "metrics"))]
+    Drone(DroneCommand),
+    /// Stops sending to a neighbor, keeping its channel so that the link can be restored
+    #[cfg(any(feature = "gui", feature = "metrics"))]
+    Unlink(NodeId),
+    /// Restores a link removed with [`InternalCommand::Unlink`]
+    #[cfg(any(feature = "gui", feature = "metrics"))]
+    Relink(NodeId),
+}
+
 pub struct BagelBomber {
     id: NodeId,
     controller_send: Sender<DroneEvent>,
@@ -26,8 +57,21 @@ pub struct BagelBomber {
     pdr: f32,
     active: bool,
     flood_history: HashSet<(NodeId, u64)>,
-    #[cfg(feature = "gui")]
-    gui_sender: Option<Sender<GUIMessage>>,
+    #[cfg(any(feature = "gui", feature = "metrics"))]
+    internal_send: Sender<InternalCommand>,
+    internal_recv: Receiver<InternalCommand>,
+    /// Links removed from the GUI, dropped once the controller removes them as well
+    #[cfg(any(feature = "gui", feature = "metrics"))]
+    unlinked: HashMap<NodeId, Sender<Packet>>,
+    #[cfg(any(feature = "gui", feature = "metrics"))]
+    gui: Option<GuiHandle>,
+    #[cfg(any(feature = "gui", feature = "metrics"))]
+    default_gui: bool,
+    #[cfg(any(feature = "gui", feature = "metrics"))]
+    explosion_style: ExplosionStyle,
+    /// Times the drops and packets reported to the GUI
+    #[cfg(any(feature = "gui", feature = "metrics"))]
+    clock: Arc<dyn Clock>,
 }
 
 impl Drone for BagelBomber {
@@ -39,6 +83,11 @@ impl Drone for BagelBomber {
         packet_send: HashMap<NodeId, Sender<Packet>>,
         pdr: f32,
     ) -> Self {
+        #[cfg(any(feature = "gui", feature = "metrics"))]
+        let (internal_send, internal_recv) = unbounded();
+        // only the GUI sends commands through the internal channel
+        #[cfg(not(any(feature = "gui", feature = "metrics")))]
+        let internal_recv = crossbeam_channel::never();
         BagelBomber {
             id,
             controller_send,
@@ -48,8 +97,20 @@ impl Drone for BagelBomber {
             pdr,
             active: false,
             flood_history: HashSet::new(),
-            #[cfg(feature = "gui")]
-            gui_sender: None,
+            #[cfg(any(feature = "gui", feature = "metrics"))]
+            internal_send,
+            internal_recv,
+            #[cfg(any(feature = "gui", feature = "metrics"))]
+            unlinked: HashMap::new(),
+            #[cfg(any(feature = "gui", feature = "metrics"))]
+            gui: None,
+            // unit tests run many simulations at once, they shouldn't fight over the default GUI
+            #[cfg(any(feature = "gui", feature = "metrics"))]
+            default_gui: cfg!(not(test)),
+            #[cfg(any(feature = "gui", feature = "metrics"))]
+            explosion_style: ExplosionStyle::default(),
+            #[cfg(any(feature = "gui", feature = "metrics"))]
+            clock: clock::system_clock(),
         }
     }
 
@@ -59,13 +120,102 @@ impl Drone for BagelBomber {
     }
 }
 
+/// Configures a [`BagelBomber`] beyond what [`Drone::new`] allows.
+///
+/// Without further configuration the drone behaves as if created with [`Drone::new`].
+pub struct BagelBomberBuilder {
+    drone: BagelBomber,
+}
+
+impl BagelBomberBuilder {
+    /// Shows the drone in the given GUI instead of the default one
+    #[cfg(any(feature = "gui", feature = "metrics"))]
+    pub fn gui(mut self, gui: GuiHandle) -> Self {
+        self.drone.gui = Some(gui);
+        self.drone.default_gui = false;
+        self
+    }
+
+    /// Keeps the drone out of every GUI
+    #[cfg(any(feature = "gui", feature = "metrics"))]
+    pub fn without_gui(mut self) -> Self {
+        self.drone.gui = None;
+        self.drone.default_gui = false;
+        self
+    }
+
+    /// How the GUI shows this drone's bagels exploding, it can be changed later from the GUI
+    #[cfg(any(feature = "gui", feature = "metrics"))]
+    pub fn explosion_style(mut self, explosion_style: ExplosionStyle) -> Self {
+        self.drone.explosion_style = explosion_style;
+        self
+    }
+
+    /// Times what the drone reports to the GUI, which should be given the same clock
+    #[cfg(any(feature = "gui", feature = "metrics"))]
+    pub fn clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.drone.clock = clock;
+        self
+    }
+
+    pub fn build(self) -> BagelBomber {
+        self.drone
+    }
+}
+
 impl BagelBomber {
+    pub fn builder(
+        id: NodeId,
+        controller_send: Sender<DroneEvent>,
+        controller_recv: Receiver<DroneCommand>,
+        packet_recv: Receiver<Packet>,
+        packet_send: HashMap<NodeId, Sender<Packet>>,
+        pdr: f32,
+    ) -> BagelBomberBuilder {
+        BagelBomberBuilder {
+            drone: BagelBomber::new(
+                id,
+                controller_send,
+                controller_recv,
+                packet_recv,
+                packet_send,
+                pdr,
+            ),
+        }
+    }
+
+    /// Lets the targets in `fuzz/` feed the drone without running it
+    #[cfg(feature = "fuzzing")]
+    #[doc(hidden)]
+    pub fn fuzz_packet(&mut self, packet: Packet) {
+        self.handle_packet(packet);
+    }
+
+    #[cfg(feature = "fuzzing")]
+    #[doc(hidden)]
+    pub fn fuzz_command(&mut self, command: DroneCommand) {
+        self.handle_command(command);
+    }
+
     fn run_internal(&mut self) {
         #[cfg(feature = "debug")]
         println!("BagelBomber {} flying", self.id);
 
-        #[cfg(all(feature = "gui", not(test)))]
-        drone_gui::add_gui(self.id, self.pdr, &mut self.gui_sender);
+        #[cfg(any(feature = "gui", feature = "metrics"))]
+        if self.default_gui {
+            self.gui = drone_gui::attach_default_gui(self.id);
+        }
+
+        #[cfg(any(feature = "gui", feature = "metrics"))]
+        drone_gui::add_gui(
+            self.id,
+            self.pdr,
+            self.explosion_style,
+            &self.packet_recv,
+            &self.packet_send,
+            self.internal_send.clone(),
+            &self.gui,
+        );
 
         while self.active {
             select_biased! {
@@ -74,6 +224,11 @@ impl BagelBomber {
                         self.handle_command(command);
                     }
                 }
+                recv(self.internal_recv) -> command_res => {
+                    if let Ok(command) = command_res {
+                        self.handle_internal_command(command);
+                    }
+                }
                 recv(self.packet_recv) -> packet_res => {
                     if let Ok(packet) = packet_res {
                         self.handle_packet(packet);
@@ -81,14 +236,29 @@ impl BagelBomber {
                 }
             }
         }
-        #[cfg(all(feature = "gui", not(test)))]
-        drone_gui::remove_gui(self.id, &mut self.gui_sender);
+        #[cfg(any(feature = "gui", feature = "metrics"))]
+        {
+            drone_gui::remove_gui(self.id, &self.gui);
+            if self.default_gui && self.gui.take().is_some() {
+                drone_gui::detach_default_gui();
+            }
+        }
     }
 
     fn handle_command(&mut self, command: DroneCommand) {
         match command {
             DroneCommand::AddSender(id, sender) => {
                 self.packet_send.insert(id, sender);
+                #[cfg(any(feature = "gui", feature = "metrics"))]
+                {
+                    self.unlinked.remove(&id);
+                    drone_gui::change_neighbors(
+                        self.id,
+                        &self.packet_send,
+                        &self.unlinked,
+                        &self.gui,
+                    );
+                }
             }
             DroneCommand::Crash => {
                 #[cfg(feature = "debug")]
@@ -97,20 +267,72 @@ impl BagelBomber {
             }
             DroneCommand::SetPacketDropRate(pdr) => {
                 self.pdr = pdr;
-                #[cfg(all(feature = "gui", not(test)))]
-                drone_gui::change_pdr(self.id, self.pdr, &self.gui_sender);
+                #[cfg(any(feature = "gui", feature = "metrics"))]
+                drone_gui::change_pdr(self.id, self.pdr, &self.gui);
             }
             DroneCommand::RemoveSender(id) => {
                 self.packet_send.remove(&id);
+                #[cfg(any(feature = "gui", feature = "metrics"))]
+                {
+                    self.unlinked.remove(&id);
+                    drone_gui::change_neighbors(
+                        self.id,
+                        &self.packet_send,
+                        &self.unlinked,
+                        &self.gui,
+                    );
+                }
             }
         }
     }
 
-    fn handle_packet(&mut self, packet: Packet) {
+    fn handle_internal_command(&mut self, command: InternalCommand) {
+        match command {
+            #[cfg(any(feature = "gui", feature = "metrics"))]
+            InternalCommand::Drone(command) => self.handle_command(command),
+            #[cfg(any(feature = "gui", feature = "metrics"))]
+            InternalCommand::Unlink(id) => {
+                if let Some(sender) = self.packet_send.remove(&id) {
+                    self.unlinked.insert(id, sender);
+                    drone_gui::change_neighbors(
+                        self.id,
+                        &self.packet_send,
+                        &self.unlinked,
+                        &self.gui,
+                    );
+                }
+            }
+            #[cfg(any(feature = "gui", feature = "metrics"))]
+            InternalCommand::Relink(id) => {
+                if let Some(sender) = self.unlinked.remove(&id) {
+                    self.packet_send.insert(id, sender);
+                    drone_gui::change_neighbors(
+                        self.id,
+                        &self.packet_send,
+                        &self.unlinked,
+                        &self.gui,
+                    );
+                }
+            }
+        }
+    }
+
+    pub(crate) fn handle_packet(&mut self, packet: Packet) {
         #[cfg(feature = "debug")]
         println!("BagelBomber {} received packet {}", self.id, packet);
 
-        match self.create_packet_handler(packet.clone()) {
+        let handler = self.create_packet_handler(packet.clone());
+
+        #[cfg(any(feature = "gui", feature = "metrics"))]
+        drone_gui::inspect_packet(
+            self.id,
+            &packet,
+            || handler.describe(),
+            self.clock.as_ref(),
+            &self.gui,
+        );
+
+        match handler {
             PacketHandler::Forward(sender) => {
                 #[cfg(feature = "debug")]
                 println!("BagelBomber {} forwarding packet", self.id);
@@ -119,6 +341,8 @@ impl BagelBomber {
             PacketHandler::Nack(nack) => {
                 #[cfg(feature = "debug")]
                 println!("BagelBomber {} sending nack {:?}", self.id, nack);
+                #[cfg(any(feature = "gui", feature = "metrics"))]
+                drone_gui::send_nack(self.id, &nack, &self.gui);
                 let fragment_index = packet.get_fragment_index();
                 if let NackType::Dropped = &nack
                 {
@@ -138,6 +362,8 @@ impl BagelBomber {
             PacketHandler::FloodRequest => {
                 #[cfg(feature = "debug")]
                 println!("BagelBomber {} handling flood request", self.id);
+                #[cfg(any(feature = "gui", feature = "metrics"))]
+                drone_gui::handle_flood(self.id, &self.gui);
                 if let PacketType::FloodRequest(request) = packet.pack_type {
                     self.handle_flood_request(packet.routing_header, packet.session_id, request);
                 }
@@ -156,43 +382,40 @@ impl BagelBomber {
         }
     }
 
-    fn create_packet_handler(&self, packet: Packet) -> PacketHandler {
+    fn create_packet_handler(&self, packet: Packet) -> PacketHandler<'_> {
         if let PacketType::FloodRequest(_) = &packet.pack_type {
             PacketHandler::FloodRequest
+        } else if !matches!(packet.pack_type, PacketType::MsgFragment(_)) {
+            // Ack, Nack and FloodResponse are never nacked, the controller delivers the ones
+            // that can't be forwarded
+            let next_hop = packet.routing_header.next_hop();
+            match next_hop.and_then(|next_hop| self.packet_send.get(&next_hop)) {
+                Some(sender) if packet.routing_header.current_hop() == Some(self.id) => {
+                    PacketHandler::Forward(sender)
+                }
+                _ => PacketHandler::SendToController,
+            }
         } else if packet.routing_header.is_empty() {
             PacketHandler::Ignore
         } else if packet.routing_header.current_hop() != Some(self.id) {
             PacketHandler::Nack(NackType::UnexpectedRecipient(self.id))
         } else if packet.routing_header.is_last_hop() {
-            if let PacketType::Nack(_) = packet.pack_type {
-                PacketHandler::Ignore
-            } else {
-                PacketHandler::Nack(NackType::DestinationIsDrone)
-            }
+            PacketHandler::Nack(NackType::DestinationIsDrone)
         } else {
             let next_hop = packet.routing_header.next_hop().unwrap();
             match self.packet_send.get(&next_hop) {
                 Some(sender) => {
-                    if let PacketType::MsgFragment(_) = &packet.pack_type {
-                        if coin_toss::toss_coin(self.pdr) {
-                            #[cfg(all(feature = "gui", not(test)))]
-                            drone_gui::drop_bagel(self.id, true, &self.gui_sender);
-                            PacketHandler::Nack(NackType::Dropped)
-                        } else {
-                            #[cfg(all(feature = "gui", not(test)))]
-                            drone_gui::drop_bagel(self.id, false, &self.gui_sender);
-                            PacketHandler::Forward(sender)
-                        }
+                    if coin_toss::toss_coin(self.pdr) {
+                        #[cfg(any(feature = "gui", feature = "metrics"))]
+                        drone_gui::drop_bagel(self.id, true, self.clock.now(), &self.gui);
+                        PacketHandler::Nack(NackType::Dropped)
                     } else {
+                        #[cfg(any(feature = "gui", feature = "metrics"))]
+                        drone_gui::drop_bagel(self.id, false, self.clock.now(), &self.gui);
                         PacketHandler::Forward(sender)
                     }
                 }
-                None => match &packet.pack_type {
-                    PacketType::Ack(_) | PacketType::Nack(_) | PacketType::FloodResponse(_) => {
-                        PacketHandler::SendToController
-                    }
-                    _ => PacketHandler::Nack(NackType::ErrorInRouting(next_hop)),
-                },
+                None => PacketHandler::Nack(NackType::ErrorInRouting(next_hop)),
             }
         }
     }
@@ -203,14 +426,18 @@ impl BagelBomber {
         current_route: SourceRoutingHeader,
         session_id: u64,
     ) {
-        let mut new_route = current_route
-            .sub_route(..current_route.hop_index + 1)
-            .unwrap()
-            .get_reversed();
-        new_route.reset_hop_index();
+        // this drone takes the place of the current hop, a misrouted packet may not be for it
+        let mut hops = current_route
+            .hops
+            .iter()
+            .take(current_route.hop_index)
+            .copied()
+            .collect::<Vec<NodeId>>();
+        hops.push(self.id);
+        hops.reverse();
         let new_packet = Packet {
             pack_type: packet_type,
-            routing_header: new_route,
+            routing_header: SourceRoutingHeader::initialize(hops),
             session_id,
         };
         self.handle_packet(new_packet);
@@ -266,6 +493,10 @@ impl BagelBomber {
     }
 
     fn finish_up(&mut self) {
+        // the neighbors wait for every channel to them to be dropped before finishing up
+        #[cfg(any(feature = "gui", feature = "metrics"))]
+        self.unlinked.clear();
+
         let receive = mem::replace(&mut self.packet_recv, unbounded().1);
 
         for incoming in receive.iter() {
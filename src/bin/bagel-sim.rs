@@ -0,0 +1,218 @@
+use bagel_bomber::sim::traffic::{TrafficProfile, TrafficReport};
+use bagel_bomber::sim::{self, Simulation};
+#[cfg(feature = "gui")]
+use bagel_bomber::{GuiConfig, GuiServer};
+use std::process::ExitCode;
+use std::time::Duration;
+
+const USAGE: &str = "\
+Usage: bagel-sim <topology.toml> [options]
+
+Options:
+  --traffic FILE    flows of the simulation (default every client to every server)
+  --rate N          fragments per second of each flow without --traffic (default 10)
+  --duration TIME   how long the clients send, e.g. 30s or 500ms (default 10s)
+  --gui             shows the drones in the GUI at http://localhost:8463";
+
+struct Args {
+    topology: String,
+    traffic: Option<String>,
+    rate: f64,
+    duration: Duration,
+    gui: bool,
+}
+
+/// Runs a topology with traffic between its clients and servers, then prints what happened,
+/// e.g. `bagel-sim topologies/examples/double-chain/topology.toml --duration 30s`
+fn main() -> ExitCode {
+    let args = std::env::args().skip(1).collect::<Vec<String>>();
+    let args = match parse_args(&args) {
+        Ok(args) => args,
+        Err(err) => {
+            eprintln!("{}\n\n{}", err, USAGE);
+            return ExitCode::from(2);
+        }
+    };
+
+    let config = match sim::load_config(&args.topology) {
+        Ok(config) => config,
+        Err(err) => {
+            eprintln!("{}: {}", args.topology, err);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    #[cfg(feature = "gui")]
+    let gui = if args.gui {
+        match GuiServer::start(GuiConfig::default()) {
+            Ok(gui) => Some(gui),
+            Err(err) => {
+                eprintln!("could not start the GUI: {}", err);
+                return ExitCode::FAILURE;
+            }
+        }
+    } else {
+        None
+    };
+    #[cfg(not(feature = "gui"))]
+    if args.gui {
+        eprintln!("bagel-sim was built without the gui feature");
+        return ExitCode::from(2);
+    }
+
+    #[cfg(feature = "gui")]
+    let simulation = match gui.as_ref() {
+        Some(gui) => Simulation::start_with_gui(&config, gui.handle()),
+        None => Simulation::start(&config),
+    };
+    #[cfg(not(feature = "gui"))]
+    let simulation = Simulation::start(&config);
+    let mut simulation = match simulation {
+        Ok(simulation) => simulation,
+        Err(err) => {
+            eprintln!("{}: {}", args.topology, err);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let profile = match args.traffic.as_ref() {
+        Some(path) => match TrafficProfile::load(path) {
+            Ok(profile) => profile,
+            Err(err) => {
+                eprintln!("{}: {}", path, err);
+                return ExitCode::FAILURE;
+            }
+        },
+        None => TrafficProfile::every_pair(&simulation, args.rate),
+    };
+
+    println!(
+        "Running {} flows for {:?}",
+        profile.flows.len(),
+        args.duration
+    );
+    let report = match profile.run(&mut simulation, args.duration) {
+        Ok(report) => report,
+        Err(err) => {
+            eprintln!("{}", err);
+            return ExitCode::FAILURE;
+        }
+    };
+    simulation.shutdown();
+    print_report(&report);
+
+    #[cfg(feature = "gui")]
+    if let Some(gui) = gui {
+        println!("\nPress enter to stop the GUI");
+        std::io::stdin().read_line(&mut String::new()).ok();
+        gui.shutdown();
+    }
+
+    ExitCode::SUCCESS
+}
+
+fn parse_args(args: &[String]) -> Result<Args, String> {
+    let mut args = args.iter();
+    let mut parsed = Args {
+        topology: args.next().ok_or("missing topology")?.clone(),
+        traffic: None,
+        rate: 10.0,
+        duration: Duration::from_secs(10),
+        gui: false,
+    };
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or(format!("missing value of {}", arg));
+        match arg.as_str() {
+            "--traffic" => parsed.traffic = Some(value()?.clone()),
+            "--rate" => {
+                let rate = value()?;
+                parsed.rate = rate
+                    .parse()
+                    .ok()
+                    .filter(|rate: &f64| rate.is_finite() && *rate > 0.0)
+                    .ok_or(format!("invalid rate \"{}\"", rate))?;
+            }
+            "--duration" => parsed.duration = duration(value()?)?,
+            "--gui" => parsed.gui = true,
+            _ => return Err(format!("unknown option \"{}\"", arg)),
+        }
+    }
+    Ok(parsed)
+}
+
+/// `30s`, `1.5s` or `500ms`
+fn duration(text: &str) -> Result<Duration, String> {
+    let seconds = match text.strip_suffix("ms") {
+        Some(millis) => millis.parse::<f64>().map(|millis| millis / 1000.0),
+        None => text.strip_suffix('s').unwrap_or(text).parse::<f64>(),
+    };
+    seconds
+        .ok()
+        .and_then(|seconds| Duration::try_from_secs_f64(seconds).ok())
+        .ok_or(format!("invalid duration \"{}\"", text))
+}
+
+fn print_report(report: &TrafficReport) {
+    println!("\nDrones");
+    println!(
+        "{:>6} {:>8} {:>8} {:>10}",
+        "id", "sent", "dropped", "shortcuts"
+    );
+    for (id, stats) in report.drones.iter() {
+        println!(
+            "{:>6} {:>8} {:>8} {:>10}",
+            id, stats.sent, stats.dropped, stats.shortcuts
+        );
+    }
+
+    println!("\nFlows");
+    println!(
+        "{:>10} {:>7} {:>7} {:>9} {:>7} {:>7} {:>10} {:>12} {:>12}",
+        "flow",
+        "sent",
+        "resent",
+        "delivered",
+        "acked",
+        "lost",
+        "unroutable",
+        "mean latency",
+        "max latency"
+    );
+    for stats in report.flows.iter() {
+        println!(
+            "{:>10} {:>7} {:>7} {:>9} {:>7} {:>7} {:>10} {:>12} {:>12}",
+            format!("{}->{}", stats.flow.from, stats.flow.to),
+            stats.sent,
+            stats.resent,
+            stats.delivered,
+            stats.acked,
+            stats.lost(),
+            stats.unroutable,
+            latency(stats.mean_latency),
+            latency(stats.max_latency)
+        );
+    }
+
+    println!(
+        "\nDelivered {} of {} fragments ({:.1}%) in {:?}",
+        report.delivered(),
+        report.sent(),
+        percent(report.delivered(), report.sent()),
+        report.elapsed
+    );
+}
+
+fn percent(part: u64, whole: u64) -> f64 {
+    if whole == 0 {
+        0.0
+    } else {
+        part as f64 * 100.0 / whole as f64
+    }
+}
+
+fn latency(latency: Option<Duration>) -> String {
+    match latency {
+        Some(latency) => format!("{:.1}ms", latency.as_secs_f64() * 1000.0),
+        None => "-".to_string(),
+    }
+}
@@ -0,0 +1,36 @@
+use bagel_bomber::topology;
+use std::process::ExitCode;
+
+/// Checks the given topology files, e.g. `check-topology topologies/examples/*/topology.toml`
+fn main() -> ExitCode {
+    let paths = std::env::args().skip(1).collect::<Vec<String>>();
+    if paths.is_empty() {
+        eprintln!("Usage: check-topology <topology.toml>...");
+        return ExitCode::from(2);
+    }
+
+    let mut valid = true;
+    for path in paths {
+        match topology::load(&path) {
+            Ok(config) => println!(
+                "{}: ok, {} drones, {} clients, {} servers",
+                path,
+                config.drone.len(),
+                config.client.len(),
+                config.server.len()
+            ),
+            Err(errors) => {
+                valid = false;
+                for error in errors {
+                    eprintln!("{}: {}", path, error);
+                }
+            }
+        }
+    }
+
+    if valid {
+        ExitCode::SUCCESS
+    } else {
+        ExitCode::FAILURE
+    }
+}
@@ -0,0 +1,180 @@
+use bagel_bomber::topology::{
+    self,
+    generate::{self, GeneratorOptions, PdrDistribution, Placement, Shape},
+};
+use std::path::PathBuf;
+use std::process::ExitCode;
+use wg_2024::network::NodeId;
+
+const USAGE: &str = "\
+Usage: generate-topology <shape> [options]
+
+Shapes:
+  double-chain --length N
+  star --drones N
+  ring --drones N
+  tree --depth N --branching N
+  grid --width N --height N
+  butterfly --stages N
+  random --drones N --probability P
+
+Options:
+  --pdr P | --pdr LOW..HIGH   fixed or uniformly distributed PDR (default 0..0.25)
+  --clients N                 (default 2)
+  --servers N                 (default 2)
+  --placement ends|random     where clients and servers are linked (default ends)
+  --seed N                    (default 0)
+  --out DIR                   (default topologies/generated/<shape>)";
+
+/// Writes `topology.toml` and `description.md`, e.g. `generate-topology grid --width 4 --height 3`
+fn main() -> ExitCode {
+    let args = std::env::args().skip(1).collect::<Vec<String>>();
+    let (options, out) = match parse_args(&args) {
+        Ok(parsed) => parsed,
+        Err(err) => {
+            eprintln!("{}\n\n{}", err, USAGE);
+            return ExitCode::from(2);
+        }
+    };
+
+    let generated = match generate::generate(&options) {
+        Ok(generated) => generated,
+        Err(err) => {
+            eprintln!("{}", err);
+            return ExitCode::FAILURE;
+        }
+    };
+    let toml = generated.to_toml();
+    if let Err(errors) = topology::parse(&toml) {
+        for error in errors {
+            eprintln!("generated topology is invalid: {}", error);
+        }
+        return ExitCode::FAILURE;
+    }
+
+    let written = std::fs::create_dir_all(&out)
+        .and_then(|_| std::fs::write(out.join("topology.toml"), toml))
+        .and_then(|_| std::fs::write(out.join("description.md"), &generated.description));
+    match written {
+        Ok(()) => {
+            println!(
+                "{}: {} drones, {} clients, {} servers",
+                out.display(),
+                generated.config.drone.len(),
+                generated.config.client.len(),
+                generated.config.server.len()
+            );
+            ExitCode::SUCCESS
+        }
+        Err(err) => {
+            eprintln!("{}: {}", out.display(), err);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn parse_args(args: &[String]) -> Result<(GeneratorOptions, PathBuf), String> {
+    let shape_name = args.first().ok_or("missing shape")?;
+    let mut options = GeneratorOptions::default();
+    let mut out = PathBuf::from("topologies/generated").join(shape_name);
+    let mut sizes = Vec::new();
+
+    let mut rest = args[1..].iter();
+    while let Some(flag) = rest.next() {
+        let value = rest
+            .next()
+            .ok_or_else(|| format!("missing value for {}", flag))?;
+        match flag.as_str() {
+            "--pdr" => options.pdr = parse_pdr(value)?,
+            "--clients" => options.clients = number(flag, value)?,
+            "--servers" => options.servers = number(flag, value)?,
+            "--placement" => {
+                options.placement = match value.as_str() {
+                    "ends" => Placement::Ends,
+                    "random" => Placement::Random,
+                    _ => return Err(format!("unknown placement \"{}\"", value)),
+                }
+            }
+            "--seed" => options.seed = number(flag, value)?,
+            "--out" => out = PathBuf::from(value),
+            _ => sizes.push((flag.as_str(), value.as_str())),
+        }
+    }
+
+    let mut option = |name: &str| -> Result<String, String> {
+        let index = sizes
+            .iter()
+            .position(|(flag, _)| flag.strip_prefix("--") == Some(name))
+            .ok_or_else(|| format!("{} needs --{}", shape_name, name))?;
+        Ok(sizes.remove(index).1.to_string())
+    };
+    // no size beyond the node ids can give a valid topology, smaller ones are checked later
+    let mut size = |name: &str| -> Result<usize, String> {
+        let flag = format!("--{}", name);
+        let size = number::<usize>(&flag, &option(name)?)?;
+        if size > NodeId::MAX as usize {
+            return Err(format!("{} must be at most {}", flag, NodeId::MAX));
+        }
+        Ok(size)
+    };
+    options.shape = match shape_name.as_str() {
+        "double-chain" => Shape::DoubleChain {
+            length: size("length")?,
+        },
+        "star" => Shape::Star {
+            drones: size("drones")?,
+        },
+        "ring" => Shape::Ring {
+            drones: size("drones")?,
+        },
+        "tree" => Shape::Tree {
+            depth: size("depth")?,
+            branching: size("branching")?,
+        },
+        "grid" => Shape::Grid {
+            width: size("width")?,
+            height: size("height")?,
+        },
+        "butterfly" => Shape::Butterfly {
+            stages: size("stages")?,
+        },
+        "random" => Shape::Random {
+            drones: size("drones")?,
+            probability: fraction("--probability", &option("probability")?)?,
+        },
+        _ => return Err(format!("unknown shape \"{}\"", shape_name)),
+    };
+    if let Some((flag, _)) = sizes.first() {
+        return Err(format!("unknown option {} for {}", flag, shape_name));
+    }
+
+    Ok((options, out))
+}
+
+fn number<T: std::str::FromStr>(flag: &str, value: &str) -> Result<T, String> {
+    value
+        .parse()
+        .map_err(|_| format!("invalid value \"{}\" for {}", value, flag))
+}
+
+/// A number between 0 and 1, which rules out NaN and infinities
+fn fraction<T: std::str::FromStr + Into<f64> + Copy>(flag: &str, value: &str) -> Result<T, String> {
+    let fraction = number::<T>(flag, value)?;
+    if !(0.0..=1.0).contains(&fraction.into()) {
+        return Err(format!(
+            "{} must be between 0 and 1, not \"{}\"",
+            flag, value
+        ));
+    }
+    Ok(fraction)
+}
+
+fn parse_pdr(value: &str) -> Result<PdrDistribution, String> {
+    match value.split_once("..") {
+        Some((low, high)) => Ok(PdrDistribution::Uniform(
+            fraction("--pdr", low)?,
+            fraction("--pdr", high)?,
+        )),
+        None => Ok(PdrDistribution::Fixed(fraction("--pdr", value)?)),
+    }
+}
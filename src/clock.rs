@@ -0,0 +1,73 @@
+//! Where the drones and the GUI read the time from.
+//!
+//! Everything defaults to [`SystemClock`]. Tests hand the same [`VirtualClock`] to the drones
+//! and the GUI, or to a simulation, and move it forward themselves.
+
+use std::fmt::Debug;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+
+pub trait Clock: Debug + Send + Sync {
+    fn now(&self) -> SystemTime;
+
+    /// Lets `duration` pass, a virtual clock moves forward without waiting
+    fn sleep(&self, duration: Duration);
+}
+
+/// The wall clock
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> SystemTime {
+        SystemTime::now()
+    }
+
+    fn sleep(&self, duration: Duration) {
+        std::thread::sleep(duration);
+    }
+}
+
+/// A clock that only moves when told to
+#[derive(Debug)]
+pub struct VirtualClock {
+    now: Mutex<SystemTime>,
+}
+
+impl VirtualClock {
+    pub fn new(start: SystemTime) -> Self {
+        VirtualClock {
+            now: Mutex::new(start),
+        }
+    }
+
+    pub fn advance(&self, duration: Duration) {
+        *self.now.lock().unwrap() += duration;
+    }
+
+    pub fn set(&self, now: SystemTime) {
+        *self.now.lock().unwrap() = now;
+    }
+}
+
+impl Default for VirtualClock {
+    /// Starts at the Unix epoch, so that times read as durations since the start
+    fn default() -> Self {
+        VirtualClock::new(SystemTime::UNIX_EPOCH)
+    }
+}
+
+impl Clock for VirtualClock {
+    fn now(&self) -> SystemTime {
+        *self.now.lock().unwrap()
+    }
+
+    fn sleep(&self, duration: Duration) {
+        self.advance(duration);
+    }
+}
+
+/// The default clock of the drones, the GUI and the simulations
+pub fn system_clock() -> Arc<dyn Clock> {
+    Arc::new(SystemClock)
+}
@@ -0,0 +1,587 @@
+//! Protocol checks that any [`Drone`] implementation can be run against, to see where drones
+//! behave differently.
+//!
+//! ```no_run
+//! # use bagel_bomber::conformance::Conformance;
+//! let report = Conformance::bagel_bomber().run();
+//! print!("{}", report);
+//! assert!(report.passed());
+//! ```
+//!
+//! Every check runs a fresh drone on its own thread, with id [`DRONE`], and only talks to it
+//! through its channels. [`Conformance::of`] builds the drones with [`Drone::new`], and
+//! [`Conformance::new`] takes a factory instead, e.g. to configure a drone with its builder:
+//!
+//! ```no_run
+//! # use bagel_bomber::conformance::Conformance;
+//! # use bagel_bomber::BagelBomber;
+//! let conformance = Conformance::new(|id, controller_send, controller_recv, packet_recv, packet_send, pdr| {
+//!     let builder = BagelBomber::builder(id, controller_send, controller_recv, packet_recv, packet_send, pdr);
+//!     Box::new(builder.without_gui().build())
+//! });
+//! ```
+
+use crate::mock_controller::{EventMatcher, MockController, RecordedEvent};
+use crossbeam_channel::{unbounded, Receiver, Sender};
+use std::any::Any;
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+use wg_2024::controller::{DroneCommand, DroneEvent};
+use wg_2024::drone::Drone;
+use wg_2024::network::{NodeId, SourceRoutingHeader};
+use wg_2024::packet::{
+    FloodRequest, Fragment, Nack, NackType, NodeType, Packet, PacketType, FRAGMENT_DSIZE,
+};
+
+/// The id of the drone under test
+pub const DRONE: NodeId = 1;
+/// The drone starts linked to these, with a PDR of 0
+const NEIGHBORS: [NodeId; 3] = [2, 3, 4];
+/// A node that is never a neighbor of the drone
+const STRANGER: NodeId = 7;
+/// The client at the start of the floods
+const INITIATOR: NodeId = 10;
+const SESSION: u64 = 42;
+const FRAGMENT: u64 = 3;
+/// How long a drone gets to settle after a command, and to show it sends nothing else
+const QUIET_TIME: Duration = Duration::from_millis(50);
+
+/// Builds the drone under test from the arguments of [`Drone::new`]
+pub type DroneFactory = dyn Fn(
+        NodeId,
+        Sender<DroneEvent>,
+        Receiver<DroneCommand>,
+        Receiver<Packet>,
+        HashMap<NodeId, Sender<Packet>>,
+        f32,
+    ) -> Box<dyn Drone>
+    + Send
+    + Sync;
+
+type CheckFn = fn(&mut Bench) -> Result<(), String>;
+
+/// Every check, in the order they run
+const CHECKS: [(&str, CheckFn); 10] = [
+    ("forwards_fragments", forwards_fragments),
+    ("nacks_dropped_fragments", nacks_dropped_fragments),
+    ("nacks_unexpected_recipient", nacks_unexpected_recipient),
+    ("nacks_destination_is_drone", nacks_destination_is_drone),
+    ("nacks_error_in_routing", nacks_error_in_routing),
+    ("floods_other_neighbors", floods_other_neighbors),
+    ("answers_known_floods", answers_known_floods),
+    (
+        "shortcuts_undeliverable_packets",
+        shortcuts_undeliverable_packets,
+    ),
+    ("follows_commands", follows_commands),
+    ("drains_queue_after_crash", drains_queue_after_crash),
+];
+
+/// The protocol checks, run against the drones of a factory
+pub struct Conformance {
+    factory: Box<DroneFactory>,
+    timeout: Duration,
+}
+
+/// How a single check went
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CheckResult {
+    pub name: &'static str,
+    /// Why the drone failed the check
+    pub outcome: Result<(), String>,
+}
+
+impl Display for CheckResult {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match &self.outcome {
+            Ok(()) => write!(f, "ok     {}", self.name),
+            Err(reason) => write!(f, "FAILED {}: {}", self.name, reason),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ConformanceReport {
+    pub results: Vec<CheckResult>,
+}
+
+impl ConformanceReport {
+    pub fn passed(&self) -> bool {
+        self.results.iter().all(|result| result.outcome.is_ok())
+    }
+
+    pub fn failures(&self) -> impl Iterator<Item = &CheckResult> {
+        self.results.iter().filter(|result| result.outcome.is_err())
+    }
+}
+
+impl Display for ConformanceReport {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        for result in self.results.iter() {
+            writeln!(f, "{}", result)?;
+        }
+        let failed = self.failures().count();
+        writeln!(
+            f,
+            "{} passed, {} failed",
+            self.results.len() - failed,
+            failed
+        )
+    }
+}
+
+impl Conformance {
+    pub fn new(
+        factory: impl Fn(
+                NodeId,
+                Sender<DroneEvent>,
+                Receiver<DroneCommand>,
+                Receiver<Packet>,
+                HashMap<NodeId, Sender<Packet>>,
+                f32,
+            ) -> Box<dyn Drone>
+            + Send
+            + Sync
+            + 'static,
+    ) -> Self {
+        Conformance {
+            factory: Box::new(factory),
+            timeout: Duration::from_secs(1),
+        }
+    }
+
+    /// Checks the drones created through [`Drone::new`].
+    ///
+    /// Whatever `new` does happens once per check: a [`crate::BagelBomber`] joins the default
+    /// GUI, which opens ports 8463 and 8464, so test suites running at the same time collide.
+    /// [`Conformance::bagel_bomber`] keeps it out of the GUI
+    pub fn of<D: Drone + 'static>() -> Self {
+        Conformance::new(
+            |id, controller_send, controller_recv, packet_recv, packet_send, pdr| {
+                Box::new(D::new(
+                    id,
+                    controller_send,
+                    controller_recv,
+                    packet_recv,
+                    packet_send,
+                    pdr,
+                ))
+            },
+        )
+    }
+
+    /// Checks [`crate::BagelBomber`] without a GUI, so no port is opened
+    pub fn bagel_bomber() -> Self {
+        Conformance::new(
+            |id, controller_send, controller_recv, packet_recv, packet_send, pdr| {
+                let builder = crate::BagelBomber::builder(
+                    id,
+                    controller_send,
+                    controller_recv,
+                    packet_recv,
+                    packet_send,
+                    pdr,
+                );
+                #[cfg(any(feature = "gui", feature = "metrics"))]
+                let builder = builder.without_gui();
+                Box::new(builder.build())
+            },
+        )
+    }
+
+    /// How long a drone may take to send what a check expects, one second by default
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// The names of the checks, in the order [`Conformance::run`] runs them
+    pub fn checks() -> impl Iterator<Item = &'static str> {
+        CHECKS.iter().map(|(name, _)| *name)
+    }
+
+    pub fn run(&self) -> ConformanceReport {
+        ConformanceReport {
+            results: CHECKS
+                .iter()
+                .map(|(name, check)| self.run_check(name, *check))
+                .collect(),
+        }
+    }
+
+    /// Runs one of [`Conformance::checks`], `None` if there's no check with that name
+    pub fn run_one(&self, name: &str) -> Option<CheckResult> {
+        CHECKS
+            .iter()
+            .find(|(check, _)| *check == name)
+            .map(|(name, check)| self.run_check(name, *check))
+    }
+
+    fn run_check(&self, name: &'static str, check: CheckFn) -> CheckResult {
+        let mut bench = Bench::new(self);
+        let outcome = check(&mut bench);
+        // only the crash check fails a drone that doesn't stop
+        bench.stop().ok();
+        // a panic explains a failure better than what the check was waiting for
+        let outcome = match bench.panic.take() {
+            Some(panic) => Err(panic),
+            None => outcome,
+        };
+        CheckResult { name, outcome }
+    }
+}
+
+/// A running drone and the channels around it
+struct Bench {
+    commands: Sender<DroneCommand>,
+    controller: MockController,
+    /// Dropped when the drone crashes, so that it can finish its queue
+    packets: Option<Sender<Packet>>,
+    neighbors: HashMap<NodeId, (Sender<Packet>, Receiver<Packet>)>,
+    handle: Option<JoinHandle<()>>,
+    /// Why the drone's thread panicked, once it stopped
+    panic: Option<String>,
+    timeout: Duration,
+}
+
+impl Bench {
+    fn new(conformance: &Conformance) -> Self {
+        let controller = MockController::new().timeout(conformance.timeout);
+        let (commands, commands_recv) = unbounded();
+        let (packets, packets_recv) = unbounded();
+        let neighbors = NEIGHBORS
+            .iter()
+            .map(|id| (*id, unbounded()))
+            .collect::<HashMap<NodeId, (Sender<Packet>, Receiver<Packet>)>>();
+        let packet_send = neighbors
+            .iter()
+            .map(|(id, (sender, _))| (*id, sender.clone()))
+            .collect();
+
+        let mut drone = (conformance.factory)(
+            DRONE,
+            controller.sender(),
+            commands_recv,
+            packets_recv,
+            packet_send,
+            0.0,
+        );
+        Bench {
+            commands,
+            controller,
+            packets: Some(packets),
+            neighbors,
+            handle: Some(thread::spawn(move || drone.run())),
+            panic: None,
+            timeout: conformance.timeout,
+        }
+    }
+
+    fn send(&self, packet: Packet) {
+        if let Some(packets) = self.packets.as_ref() {
+            packets.send(packet).ok();
+        }
+    }
+
+    /// Sends a command and gives the drone time to apply it before the next packet
+    fn command(&self, command: DroneCommand) {
+        self.commands.send(command).ok();
+        thread::sleep(QUIET_TIME);
+    }
+
+    /// The next packet the drone sends to a neighbor
+    fn received_by(&self, neighbor: NodeId) -> Result<Packet, String> {
+        self.neighbors[&neighbor]
+            .1
+            .recv_timeout(self.timeout)
+            .map_err(|_| format!("nothing was sent to {} within {:?}", neighbor, self.timeout))
+    }
+
+    /// A nack for the test fragment, sent back to the neighbor it came from
+    fn expect_nack(&self, neighbor: NodeId, nack_type: NackType) -> Result<(), String> {
+        let packet = self.received_by(neighbor)?;
+        let expected = PacketType::Nack(Nack {
+            fragment_index: FRAGMENT,
+            nack_type,
+        });
+        if packet.pack_type != expected {
+            return Err(format!(
+                "expected {:?} sent to {}, got {:?}",
+                expected, neighbor, packet.pack_type
+            ));
+        }
+        expect_route(&packet, &[DRONE, neighbor], 1)?;
+        expect_session(&packet)
+    }
+
+    fn expect_event(&self, matcher: EventMatcher) -> Result<RecordedEvent, String> {
+        self.controller.wait_for(&matcher)
+    }
+
+    /// Nothing else reaches the neighbors, and nothing was handed to the controller
+    fn expect_quiet(&self) -> Result<(), String> {
+        thread::sleep(QUIET_TIME);
+        for (id, (_, receiver)) in self.neighbors.iter() {
+            if let Ok(packet) = receiver.try_recv() {
+                return Err(format!("unexpected {:?} sent to {}", packet, id));
+            }
+        }
+        match self.controller.matching(&EventMatcher::shortcut()).first() {
+            Some(shortcut) => Err(format!("unexpected {:?}", shortcut.event)),
+            None => Ok(()),
+        }
+    }
+
+    /// Forgets what the drone sent so far
+    fn clear(&self) {
+        thread::sleep(QUIET_TIME);
+        for (_, receiver) in self.neighbors.values() {
+            receiver.try_iter().for_each(drop);
+        }
+        self.controller.clear();
+    }
+
+    /// Crashes the drone and waits for it to stop, a drone that doesn't is left running
+    fn stop(&mut self) -> Result<(), String> {
+        let handle = match self.handle.take() {
+            Some(handle) => handle,
+            None => return Ok(()),
+        };
+        self.commands.send(DroneCommand::Crash).ok();
+        self.packets = None;
+        let deadline = Instant::now() + self.timeout;
+        while !handle.is_finished() && Instant::now() < deadline {
+            thread::sleep(Duration::from_millis(1));
+        }
+        if !handle.is_finished() {
+            return Err(format!(
+                "the drone didn't stop within {:?} of crashing",
+                self.timeout
+            ));
+        }
+        handle.join().map_err(|panic| {
+            let reason = format!("the drone panicked: {}", panic_message(panic));
+            self.panic = Some(reason.clone());
+            reason
+        })
+    }
+}
+
+fn panic_message(panic: Box<dyn Any + Send>) -> String {
+    match panic.downcast::<String>() {
+        Ok(message) => *message,
+        Err(panic) => match panic.downcast::<&str>() {
+            Ok(message) => message.to_string(),
+            Err(_) => "no message".to_string(),
+        },
+    }
+}
+
+fn expect_route(packet: &Packet, hops: &[NodeId], hop_index: usize) -> Result<(), String> {
+    let header = &packet.routing_header;
+    if header.hops != hops || header.hop_index != hop_index {
+        return Err(format!(
+            "expected route {:?} at hop {}, got {:?} at hop {}",
+            hops, hop_index, header.hops, header.hop_index
+        ));
+    }
+    Ok(())
+}
+
+fn expect_session(packet: &Packet) -> Result<(), String> {
+    if packet.session_id != SESSION {
+        return Err(format!(
+            "expected session {}, got {}",
+            SESSION, packet.session_id
+        ));
+    }
+    Ok(())
+}
+
+/// The test fragment, at the drone's hop of the given route
+fn fragment(hops: Vec<NodeId>) -> Packet {
+    Packet::new_fragment(
+        SourceRoutingHeader { hop_index: 1, hops },
+        SESSION,
+        Fragment::new(FRAGMENT, FRAGMENT + 1, [0; FRAGMENT_DSIZE]),
+    )
+}
+
+fn ack(hops: Vec<NodeId>) -> Packet {
+    Packet::new_ack(
+        SourceRoutingHeader { hop_index: 1, hops },
+        SESSION,
+        FRAGMENT,
+    )
+}
+
+/// A flood of [`INITIATOR`] that went through `path` before reaching the drone
+fn flood_request(path: &[NodeId]) -> Packet {
+    let mut path_trace = vec![(INITIATOR, NodeType::Client)];
+    path_trace.extend(path.iter().map(|id| (*id, NodeType::Drone)));
+    Packet::new_flood_request(
+        SourceRoutingHeader::empty_route(),
+        SESSION,
+        FloodRequest {
+            flood_id: 1,
+            initiator_id: INITIATOR,
+            path_trace,
+        },
+    )
+}
+
+fn forwards_fragments(bench: &mut Bench) -> Result<(), String> {
+    let packet = fragment(vec![2, DRONE, 3]);
+    bench.send(packet.clone());
+    let forwarded = bench.received_by(3)?;
+    if forwarded.pack_type != packet.pack_type {
+        return Err(format!("the fragment changed to {:?}", forwarded.pack_type));
+    }
+    expect_route(&forwarded, &[2, DRONE, 3], 2)?;
+    expect_session(&forwarded)?;
+    bench.expect_event(
+        EventMatcher::packet_sent()
+            .session(SESSION)
+            .fragment(FRAGMENT),
+    )?;
+    bench.expect_quiet()
+}
+
+fn nacks_dropped_fragments(bench: &mut Bench) -> Result<(), String> {
+    bench.command(DroneCommand::SetPacketDropRate(1.0));
+    let packet = fragment(vec![2, DRONE, 3]);
+    bench.send(packet.clone());
+    bench.expect_nack(2, NackType::Dropped)?;
+    let dropped = bench.expect_event(EventMatcher::packet_dropped().session(SESSION))?;
+    if dropped.packet().pack_type != packet.pack_type {
+        return Err(format!(
+            "expected the fragment in PacketDropped, got {:?}",
+            dropped.packet().pack_type
+        ));
+    }
+    bench.expect_quiet()
+}
+
+fn nacks_unexpected_recipient(bench: &mut Bench) -> Result<(), String> {
+    bench.send(fragment(vec![2, STRANGER, 3]));
+    bench.expect_nack(2, NackType::UnexpectedRecipient(DRONE))?;
+    bench.expect_quiet()
+}
+
+fn nacks_destination_is_drone(bench: &mut Bench) -> Result<(), String> {
+    bench.send(fragment(vec![2, DRONE]));
+    bench.expect_nack(2, NackType::DestinationIsDrone)?;
+    bench.expect_quiet()
+}
+
+fn nacks_error_in_routing(bench: &mut Bench) -> Result<(), String> {
+    bench.send(fragment(vec![2, DRONE, STRANGER]));
+    bench.expect_nack(2, NackType::ErrorInRouting(STRANGER))?;
+    bench.expect_quiet()
+}
+
+fn floods_other_neighbors(bench: &mut Bench) -> Result<(), String> {
+    bench.send(flood_request(&[2]));
+    let path_trace = vec![
+        (INITIATOR, NodeType::Client),
+        (2, NodeType::Drone),
+        (DRONE, NodeType::Drone),
+    ];
+    for neighbor in [3, 4] {
+        let packet = bench.received_by(neighbor)?;
+        match packet.pack_type {
+            PacketType::FloodRequest(request) if request.path_trace == path_trace => {}
+            other => {
+                return Err(format!(
+                    "expected a flood request with path {:?} sent to {}, got {:?}",
+                    path_trace, neighbor, other
+                ))
+            }
+        }
+    }
+    // the neighbor the flood came from doesn't get it back
+    bench.expect_quiet()
+}
+
+fn answers_known_floods(bench: &mut Bench) -> Result<(), String> {
+    bench.send(flood_request(&[2]));
+    bench.clear();
+    bench.send(flood_request(&[3]));
+    let packet = bench.received_by(3)?;
+    let path_trace = vec![
+        (INITIATOR, NodeType::Client),
+        (3, NodeType::Drone),
+        (DRONE, NodeType::Drone),
+    ];
+    match &packet.pack_type {
+        PacketType::FloodResponse(response)
+            if response.flood_id == 1 && response.path_trace == path_trace => {}
+        other => {
+            return Err(format!(
+                "expected a flood response with path {:?} sent to 3, got {:?}",
+                path_trace, other
+            ))
+        }
+    }
+    expect_route(&packet, &[DRONE, 3, INITIATOR], 1)?;
+    bench.expect_quiet()
+}
+
+fn shortcuts_undeliverable_packets(bench: &mut Bench) -> Result<(), String> {
+    let packet = ack(vec![2, DRONE, STRANGER]);
+    bench.send(packet.clone());
+    let shortcut = bench.expect_event(EventMatcher::shortcut().session(SESSION))?;
+    if *shortcut.packet() != packet {
+        return Err(format!(
+            "expected the ack unchanged in ControllerShortcut, got {:?}",
+            shortcut.packet()
+        ));
+    }
+    bench.controller.clear();
+    bench.expect_quiet()
+}
+
+fn follows_commands(bench: &mut Bench) -> Result<(), String> {
+    bench.command(DroneCommand::RemoveSender(3));
+    bench.send(fragment(vec![2, DRONE, 3]));
+    bench
+        .expect_nack(2, NackType::ErrorInRouting(3))
+        .map_err(|reason| format!("after RemoveSender(3): {}", reason))?;
+
+    let (sender, receiver) = unbounded();
+    bench.command(DroneCommand::AddSender(STRANGER, sender));
+    bench.send(fragment(vec![2, DRONE, STRANGER]));
+    receiver
+        .recv_timeout(bench.timeout)
+        .map_err(|_| format!("after AddSender({}): nothing was forwarded", STRANGER))?;
+
+    bench.command(DroneCommand::SetPacketDropRate(1.0));
+    bench.send(fragment(vec![2, DRONE, 4]));
+    bench
+        .expect_nack(2, NackType::Dropped)
+        .map_err(|reason| format!("after SetPacketDropRate(1.0): {}", reason))?;
+    bench.expect_quiet()
+}
+
+/// Fragments still queued when the drone crashes are nacked, acks still go through and the
+/// drone stops once nobody can send it packets anymore
+fn drains_queue_after_crash(bench: &mut Bench) -> Result<(), String> {
+    bench.command(DroneCommand::Crash);
+    bench.send(fragment(vec![2, DRONE, 3]));
+    let packet = ack(vec![2, DRONE, 3]);
+    bench.send(packet.clone());
+    bench.packets = None;
+
+    bench.expect_nack(2, NackType::ErrorInRouting(DRONE))?;
+    let forwarded = bench.received_by(3)?;
+    if forwarded.pack_type != packet.pack_type {
+        return Err(format!(
+            "expected the ack forwarded to 3, got {:?}",
+            forwarded.pack_type
+        ));
+    }
+    bench.stop()?;
+    bench.expect_quiet()
+}
@@ -1,261 +1,421 @@
+use crossbeam_channel::Receiver;
 use crossbeam_channel::Sender;
-use crossbeam_channel::{unbounded, Receiver};
 use lazy_static::lazy_static;
-use std::collections::VecDeque;
-use std::net::{TcpListener, TcpStream};
+use rand::Rng;
+use serde::Serialize;
+use server::GuiState;
+use crate::bagel_bomber::InternalCommand;
+use crate::clock::Clock;
+use std::collections::{BTreeSet, VecDeque};
 use std::time::{Duration, SystemTime};
-use std::{collections::HashMap, io::Cursor, mem, sync::Mutex, thread, thread::JoinHandle};
-use tiny_http::{Header, Method, Request, Response, Server};
-use tungstenite::{Message, WebSocket};
+use std::{collections::HashMap, io::Cursor, sync::Mutex};
+use tiny_http::{Header, Method, Request, Response};
+use wg_2024::controller::DroneCommand;
 use wg_2024::network::NodeId;
+use wg_2024::packet::{NackType, Packet};
+
+mod api;
+#[cfg(feature = "gui")]
+mod assets;
+mod auth;
+mod inspector;
+mod metrics;
+#[cfg(feature = "gui")]
+mod overview;
+mod recording;
+mod server;
+#[cfg(feature = "tui")]
+mod tui;
+#[cfg(feature = "gui")]
+mod web_socket;
+
+pub use inspector::InspectedPacket;
+pub use recording::GuiRecorder;
+pub use server::{GuiConfig, GuiError, GuiHandle, GuiServer};
+#[cfg(feature = "tui")]
+pub use tui::TuiDashboard;
 
 lazy_static! {
-    static ref SENDER: Mutex<Option<Sender<GUIMessage>>> = Mutex::new(None);
+    /// The GUI of the drones created through [`wg_2024::drone::Drone::new`], started by the
+    /// first of them and shut down when the last one leaves
+    static ref DEFAULT_GUI: Mutex<DefaultGui> = Mutex::new(DefaultGui::default());
 }
-lazy_static! {
-    static ref GUIS: Mutex<HashMap<NodeId, DroneGUI>> = Mutex::new(HashMap::new());
+
+#[derive(Default)]
+struct DefaultGui {
+    server: Option<GuiServer>,
+    error: Option<GuiError>,
+    failures: usize,
 }
-lazy_static! {
-    static ref SERVER_JOIN_HANDLES: Mutex<Vec<JoinHandle<()>>> = Mutex::new(Vec::new());
+
+/// How many drops each drone GUI remembers, used for the history chart and the API
+#[derive(Clone, Copy, Debug)]
+pub enum DropRetention {
+    /// Keep only the latest drops
+    Count(usize),
+    /// Keep the drops that happened within the window, at most [`MAX_WINDOW_DROPS`] of them
+    Window(Duration),
 }
 
-const DRONE_GUI_PORT: u16 = 8463;
+/// How many drops a [`DropRetention::Window`] keeps at most, however busy the drone is
+pub const MAX_WINDOW_DROPS: usize = 10_000;
+
+/// How the drone page shows a bagel exploding
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ExplosionStyle {
+    /// A plain BOOM
+    #[default]
+    Classic,
+    /// Confetti and flour everywhere
+    ConfettiFlourBurst,
+    /// The field shakes and bagel crumbs fly
+    BagelPocalypse,
+}
 
-pub enum GUIMessage {
-    DroneAdded(NodeId, f32),
-    DroneRemoved(NodeId),
-    PDRChanged(NodeId, f32),
-    BagelDropped(NodeId, bool),
-}
-
-pub fn add_gui(id: NodeId, pdr: f32, sender: &mut Option<Sender<GUIMessage>>) {
-    let mut static_sender = SENDER.lock().unwrap();
-    if let Some(static_sender) = static_sender.as_ref() {
-        static_sender.send(GUIMessage::DroneAdded(id, pdr)).ok();
-        *sender = Some(static_sender.clone());
-    } else {
-        let (send, recv) = unbounded();
-        send.send(GUIMessage::DroneAdded(id, pdr)).ok();
-        *sender = Some(send.clone());
-        *static_sender = Some(send);
-        run(recv);
+impl ExplosionStyle {
+    #[cfg(feature = "gui")]
+    fn name(&self) -> &'static str {
+        match self {
+            ExplosionStyle::Classic => "classic",
+            ExplosionStyle::ConfettiFlourBurst => "confetti-flour-burst",
+            ExplosionStyle::BagelPocalypse => "bagel-pocalypse",
+        }
     }
 }
 
-pub fn remove_gui(id: NodeId, sender: &mut Option<Sender<GUIMessage>>) {
-    if GUIS.lock().unwrap().len() == 1 {
-        *GUIS.lock().unwrap() = HashMap::new();
-        *SENDER.lock().unwrap() = None;
-        let mut server_join_handles = SERVER_JOIN_HANDLES.lock().unwrap();
-        for handle in mem::take(&mut *server_join_handles).into_iter() {
-            handle.join().ok();
+impl std::str::FromStr for ExplosionStyle {
+    type Err = ();
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name {
+            "classic" => Ok(ExplosionStyle::Classic),
+            "confetti-flour-burst" => Ok(ExplosionStyle::ConfettiFlourBurst),
+            "bagel-pocalypse" => Ok(ExplosionStyle::BagelPocalypse),
+            _ => Err(()),
         }
-    } else if let Some(sender) = sender.as_ref() {
-        sender.send(GUIMessage::DroneRemoved(id)).ok();
     }
 }
 
-pub fn change_pdr(id: NodeId, pdr: f32, sender: &Option<Sender<GUIMessage>>) {
-    if let Some(sender) = sender.as_ref() {
-        sender.send(GUIMessage::PDRChanged(id, pdr)).ok();
-    }
+pub enum GUIMessage {
+    DroneAdded(Box<DroneGUI>),
+    DroneRemoved(NodeId),
+    PDRChanged(NodeId, f32),
+    /// The neighbors of the drone and the ones it can relink, only their ids so that the
+    /// GUI never keeps a drone's channel alive
+    NeighborsChanged(NodeId, BTreeSet<NodeId>, BTreeSet<NodeId>),
+    BagelDropped(NodeId, bool, SystemTime),
+    NackSent(NodeId, &'static str),
+    FloodHandled(NodeId),
+    PacketInspected(NodeId, Box<InspectedPacket>),
+}
+
+/// How the default GUI is doing, see [`default_gui_status`]
+#[derive(Clone, Debug)]
+pub struct DefaultGuiStatus {
+    pub running: bool,
+    /// Why the default GUI couldn't start the last time a drone tried
+    pub error: Option<GuiError>,
+    /// How many drones took off without the default GUI because it couldn't start
+    pub drones_without_gui: usize,
 }
 
-pub fn drop_bagel(id: NodeId, dropped: bool, sender: &Option<Sender<GUIMessage>>) {
-    if let Some(sender) = sender.as_ref() {
-        sender.send(GUIMessage::BagelDropped(id, dropped)).ok();
+pub fn default_gui_status() -> DefaultGuiStatus {
+    let default_gui = DEFAULT_GUI.lock().unwrap();
+    DefaultGuiStatus {
+        running: default_gui.server.is_some(),
+        error: default_gui.error.clone(),
+        drones_without_gui: default_gui.failures,
     }
 }
 
-fn run(receiver: Receiver<GUIMessage>) {
-    let receiver_handle = thread::spawn(move || {
-        receiver_daemon(receiver);
-    });
-    let http_daemon_handle = thread::spawn(move || {
-        http_daemon();
-    });
-    let web_socket_daemon_handle = thread::spawn(move || {
-        web_socket_daemon();
-    });
-    let mut server_join_handles = SERVER_JOIN_HANDLES.lock().unwrap();
-    server_join_handles.push(receiver_handle);
-    server_join_handles.push(http_daemon_handle);
-    server_join_handles.push(web_socket_daemon_handle);
-}
-
-fn receiver_daemon(receiver: Receiver<GUIMessage>) {
-    for message in receiver {
-        handle_message(message);
-        if GUIS.lock().unwrap().is_empty() {
-            break;
+/// Starts the default GUI if needed, the drone keeps flying without a GUI if it can't start
+pub fn attach_default_gui(id: NodeId) -> Option<GuiHandle> {
+    let mut default_gui = DEFAULT_GUI.lock().unwrap();
+    if default_gui.server.is_none() {
+        match GuiServer::start(GuiConfig::default()) {
+            Ok(server) => {
+                default_gui.server = Some(server);
+                default_gui.error = None;
+            }
+            Err(err) => {
+                eprintln!("BagelBomber {} flying without GUI: {}", id, err);
+                default_gui.error = Some(err);
+                default_gui.failures += 1;
+                return None;
+            }
         }
     }
-    *SENDER.lock().unwrap() = None;
+    let server = default_gui.server.as_mut()?;
+    server.attach();
+    Some(server.handle())
 }
 
-fn http_daemon() {
-    println!("Visit http://localhost:{}", DRONE_GUI_PORT);
-    let http_server = Server::http(format!("0.0.0.0:{}", DRONE_GUI_PORT)).unwrap();
-    loop {
-        if let Ok(Some(request)) = http_server.try_recv() {
-            handle_http_request(request, GUIS.lock().unwrap().clone());
+pub fn detach_default_gui() {
+    let mut default_gui = DEFAULT_GUI.lock().unwrap();
+    if default_gui.server.as_mut().is_some_and(|server| server.detach()) {
+        if let Some(server) = default_gui.server.take() {
+            server.shutdown();
         }
+    }
+}
 
-        if SENDER.lock().unwrap().is_none() {
-            break;
-        }
+pub fn add_gui(
+    id: NodeId,
+    pdr: f32,
+    explosion_style: ExplosionStyle,
+    packet_queue: &Receiver<Packet>,
+    neighbors: &HashMap<NodeId, Sender<Packet>>,
+    commands: Sender<InternalCommand>,
+    gui: &Option<GuiHandle>,
+) {
+    if let Some(gui) = gui.as_ref() {
+        gui.send(GUIMessage::DroneAdded(Box::new(DroneGUI::new(
+            id,
+            pdr,
+            explosion_style,
+            commands,
+            packet_queue.clone(),
+        ))));
+        gui.send(GUIMessage::NeighborsChanged(
+            id,
+            neighbors.keys().copied().collect(),
+            BTreeSet::new(),
+        ));
     }
+}
 
-    #[cfg(feature = "debug")]
-    println!("HTTP server shutting down");
-}
-
-fn web_socket_daemon() {
-    let tcp_listener = TcpListener::bind(format!("0.0.0.0:{}", DRONE_GUI_PORT + 1)).unwrap();
-    tcp_listener.set_nonblocking(true).ok();
-    let starting_time = SystemTime::now() - Duration::from_secs(5);
-    loop {
-        let stream = tcp_listener.accept();
-        match stream {
-            Ok((stream, _)) => {
-                let web_socket_updates = thread::spawn(move || {
-                    if let Ok(web_socket_message) = tungstenite::accept(stream) {
-                        handle_web_socket_connection(web_socket_message, starting_time);
-                    }
-                });
-                SERVER_JOIN_HANDLES.lock().unwrap().push(web_socket_updates);
-            }
-            Err(err) if err.kind() == std::io::ErrorKind::WouldBlock => {}
-            Err(_) => {}
-        }
+pub fn remove_gui(id: NodeId, gui: &Option<GuiHandle>) {
+    if let Some(gui) = gui.as_ref() {
+        gui.send(GUIMessage::DroneRemoved(id));
+    }
+}
 
-        if SENDER.lock().unwrap().is_none() {
-            break;
-        }
+pub fn change_pdr(id: NodeId, pdr: f32, gui: &Option<GuiHandle>) {
+    if let Some(gui) = gui.as_ref() {
+        gui.send(GUIMessage::PDRChanged(id, pdr));
     }
+}
 
-    #[cfg(feature = "debug")]
-    println!("WebSocket server shutting down");
+pub fn change_neighbors(
+    id: NodeId,
+    neighbors: &HashMap<NodeId, Sender<Packet>>,
+    unlinked: &HashMap<NodeId, Sender<Packet>>,
+    gui: &Option<GuiHandle>,
+) {
+    if let Some(gui) = gui.as_ref() {
+        gui.send(GUIMessage::NeighborsChanged(
+            id,
+            neighbors.keys().copied().collect(),
+            unlinked.keys().copied().collect(),
+        ));
+    }
 }
 
-fn handle_web_socket_connection(mut web_socket: WebSocket<TcpStream>, starting_time: SystemTime) {
-    let message = loop {
-        if let Ok(message) = web_socket.read() {
-            break message;
-        }
-        thread::sleep(Duration::from_millis(10));
-    };
+pub fn drop_bagel(id: NodeId, dropped: bool, time: SystemTime, gui: &Option<GuiHandle>) {
+    if let Some(gui) = gui.as_ref() {
+        gui.send(GUIMessage::BagelDropped(id, dropped, time));
+    }
+}
 
-    if let Ok(text) = message.into_text() {
-        if let Ok(id) = text.parse::<NodeId>() {
-            let mut last_check = SystemTime::now() - Duration::from_secs(10);
-            let mut last_pdr = 0.0;
-            while let Some(gui) = GUIS.lock().unwrap().get(&id) {
-                let drops = gui
-                    .drops
-                    .iter()
-                    .filter(|drop| drop.time > last_check)
-                    .map(|drop| {
-                        format!(
-                            "{{ \"exploded\": {}, \"time\": {} }}",
-                            drop.exploded,
-                            drop.time
-                                .duration_since(starting_time)
-                                .unwrap()
-                                .as_secs_f32()
-                        )
-                    })
-                    .collect::<Vec<String>>()
-                    .join(", ");
-
-                if gui.pdr != last_pdr || !drops.is_empty() {
-                    let response = format!("{{ \"pdr\": {}, \"drops\": [ {} ] }}", gui.pdr, drops);
-
-                    if web_socket.write(Message::Text(response.into())).is_err() {
-                        break;
-                    }
-
-                    web_socket.flush().ok();
-
-                    last_check = SystemTime::now();
-                    last_pdr = gui.pdr;
-                }
+pub fn send_nack(id: NodeId, nack: &NackType, gui: &Option<GuiHandle>) {
+    if let Some(gui) = gui.as_ref() {
+        gui.send(GUIMessage::NackSent(id, metrics::nack_label(nack)));
+    }
+}
 
-                thread::sleep(Duration::from_millis(200));
-            }
+/// The decision is only described, and the clock only read, when there is a GUI to tell
+pub fn inspect_packet(
+    id: NodeId,
+    packet: &Packet,
+    decision: impl FnOnce() -> String,
+    clock: &dyn Clock,
+    gui: &Option<GuiHandle>,
+) {
+    if let Some(gui) = gui.as_ref() {
+        gui.send(GUIMessage::PacketInspected(
+            id,
+            Box::new(InspectedPacket::new(packet, decision(), clock.now())),
+        ));
+    }
+}
 
-            // TODO: this doesn't seem to work
-            #[cfg(feature = "debug")]
-            println!("WebSocket connection closed");
-        }
+pub fn handle_flood(id: NodeId, gui: &Option<GuiHandle>) {
+    if let Some(gui) = gui.as_ref() {
+        gui.send(GUIMessage::FloodHandled(id));
     }
 }
 
-fn handle_http_request(request: Request, guis: HashMap<NodeId, DroneGUI>) {
-    let handle = thread::spawn(move || {
-        let method = request.method();
-        let path = request.url();
-
-        let response = match (method, path) {
-            (Method::Get, "/") => handle_root(guis),
-            (Method::Get, "/style") => handle_style(),
-            (Method::Get, "/script") => handle_script(),
-            (Method::Get, "/bagel.png") => handle_icon(),
-            (Method::Get, path)
-                if path.starts_with("/")
-                    && path[1..].parse::<NodeId>().is_ok()
-                    && guis.contains_key(&path[1..].parse().unwrap()) =>
-            {
-                let id = path[1..].parse::<NodeId>().unwrap();
-                let drone_gui = guis.get(&id).unwrap();
-                handle_drone(drone_gui)
-            }
-            _ => handle_not_found(),
-        };
+fn handle_http_request(mut request: Request, state: &GuiState) {
+    let method = request.method().clone();
+    let url = request.url().to_string();
+    let (path, query) = url.split_once('?').unwrap_or((url.as_str(), ""));
 
-        request.respond(response).ok();
-    });
+    let credentials = auth::Credentials::from_request(&request, query);
+    let scope = credentials.scope(state);
+    let remembered_token = auth::token_cookie(&credentials, state);
 
-    SERVER_JOIN_HANDLES.lock().unwrap().push(handle);
+    let response = match (&method, path) {
+        (Method::Post, _) if scope < auth::Scope::Control => {
+            handle_control_error(401, "Missing or invalid control token")
+        }
+        (_, _) if scope < auth::Scope::Read => auth::handle_unauthorized(),
+        #[cfg(feature = "gui")]
+        (Method::Get, "/") if state.recording.is_some() => recording::handle_playback(),
+        #[cfg(feature = "gui")]
+        (Method::Get, "/recording") if state.recording.is_some() => {
+            recording::handle_recording(state.recording.as_deref().unwrap_or("[]"))
+        }
+        #[cfg(feature = "gui")]
+        (Method::Get, "/playback-script") => state.assets.serve(&assets::PLAYBACK_SCRIPT),
+        #[cfg(feature = "gui")]
+        (Method::Get, "/style") => state.assets.serve(&assets::STYLE),
+        #[cfg(feature = "gui")]
+        (Method::Get, "/script") => state.assets.serve(&assets::SCRIPT),
+        #[cfg(feature = "gui")]
+        (Method::Get, "/bagel.png") => state.assets.serve(&assets::ICON),
+        #[cfg(feature = "gui")]
+        (Method::Get, "/inspector-script") => state.assets.serve(&assets::INSPECTOR_SCRIPT),
+        #[cfg(feature = "gui")]
+        (Method::Get, "/overview") => overview::handle_overview(state.web_socket_port),
+        #[cfg(feature = "gui")]
+        (Method::Get, "/overview-script") => state.assets.serve(&assets::OVERVIEW_SCRIPT),
+        #[cfg(feature = "gui")]
+        (Method::Get, "/live-reload") => state.assets.serve_live_reload(),
+        #[cfg(feature = "gui")]
+        (Method::Get, "/assets-version") => state.assets.serve_version(),
+        (Method::Get, path) => handle_drone_request(path, query, &state.guis.lock().unwrap(), state),
+        (Method::Post, path) => handle_control(&mut request, path, state),
+        _ => handle_not_found(),
+    };
+
+    match remembered_token {
+        Some(cookie) => request.respond(response.with_header(cookie)).ok(),
+        None => request.respond(response).ok(),
+    };
 }
 
-fn handle_root(guis: HashMap<NodeId, DroneGUI>) -> Response<Cursor<Vec<u8>>> {
+/// The pages built from the drones, answered while holding the lock on them
+fn handle_drone_request(
+    path: &str,
+    query: &str,
+    guis: &HashMap<NodeId, DroneGUI>,
+    #[cfg_attr(not(feature = "gui"), allow(unused_variables))] state: &GuiState,
+) -> Response<Cursor<Vec<u8>>> {
+    match path {
+        "/metrics" => metrics::handle_metrics(guis),
+        path if path.starts_with("/api/") => api::handle_api(path, query, guis),
+        #[cfg(feature = "gui")]
+        "/" => handle_root(guis),
+        #[cfg(feature = "gui")]
+        path if inspector::inspected_drone(path, guis).is_some() => {
+            inspector::handle_inspector(inspector::inspected_drone(path, guis).unwrap())
+        }
+        #[cfg(feature = "gui")]
+        path if path.starts_with("/")
+            && path[1..].parse::<NodeId>().is_ok()
+            && guis.contains_key(&path[1..].parse().unwrap()) =>
+        {
+            let id = path[1..].parse::<NodeId>().unwrap();
+            let drone_gui = guis.get(&id).unwrap();
+            handle_drone(drone_gui, state)
+        }
+        _ => handle_not_found(),
+    }
+}
+
+#[cfg(feature = "gui")]
+fn handle_root(guis: &HashMap<NodeId, DroneGUI>) -> Response<Cursor<Vec<u8>>> {
     let html_body = format!(
-        "<h1>Bagel Bomber GUI</h1><div class=\"drone-list\">{}</div>",
+        "<h1>Bagel Bomber GUI</h1><div class=\"drone-list\">\
+        <a class=\"drone-link\" href=\"/overview\">Mission Control</a>{}</div>",
         guis.values().map(|gui| gui.anchor()).collect::<String>()
     );
     Response::from_string(wrap_html("Bagel Bomber GUI", html_body))
         .with_header("Content-Type: text/html".parse::<Header>().unwrap())
 }
 
-const fn icon_file() -> &'static [u8] {
-    include_bytes!("../assets/bagel.png")
+#[cfg(feature = "gui")]
+fn handle_drone(drone_gui: &DroneGUI, state: &GuiState) -> Response<Cursor<Vec<u8>>> {
+    drone_gui.drone_page(state.web_socket_port)
 }
 
-fn handle_icon() -> Response<Cursor<Vec<u8>>> {
-    Response::from_data(icon_file())
-        .with_header("Content-Type: image/png".parse::<Header>().unwrap())
-}
+fn handle_control(request: &mut Request, path: &str, state: &GuiState) -> Response<Cursor<Vec<u8>>> {
+    // the body is read before locking, a slow client mustn't hold up the drones
+    let mut body = String::new();
+    if request.as_reader().read_to_string(&mut body).is_err() {
+        return handle_control_error(400, "Unreadable request body");
+    }
+    let body = body.trim();
 
-const fn style_file() -> &'static str {
-    include_str!("../assets/style.css")
-}
+    let segments = path.trim_matches('/').split('/').collect::<Vec<&str>>();
+    let mut guis = state.guis.lock().unwrap();
+    let drone_gui = match segments[0].parse::<NodeId>().ok().and_then(|id| guis.get_mut(&id)) {
+        Some(drone_gui) => drone_gui,
+        None => return handle_not_found(),
+    };
 
-fn handle_style() -> Response<Cursor<Vec<u8>>> {
-    Response::from_string(style_file()).with_header("Content-Type: text/css".parse::<Header>().unwrap())
-}
+    let command = match &segments[1..] {
+        ["pdr"] => match body.parse::<f32>() {
+            Ok(pdr) if (0.0..=1.0).contains(&pdr) => {
+                InternalCommand::Drone(DroneCommand::SetPacketDropRate(pdr))
+            }
+            _ => return handle_control_error(400, "PDR must be a number between 0 and 1"),
+        },
+        ["crash"] => InternalCommand::Drone(DroneCommand::Crash),
+        // only the GUI cares about the explosion style, so the drone isn't bothered
+        ["explosion-style"] => match body.parse::<ExplosionStyle>() {
+            Ok(style) => {
+                drone_gui.explosion_style = style;
+                return Response::from_string("OK")
+                    .with_header("Content-Type: text/plain".parse::<Header>().unwrap());
+            }
+            Err(_) => {
+                return handle_control_error(
+                    400,
+                    "Style must be classic, confetti-flour-burst or bagel-pocalypse",
+                )
+            }
+        },
+        // the GUI holds no channels, so it can only give back a link it took away
+        ["restore-neighbor"] => match body.parse::<NodeId>() {
+            Ok(id) if drone_gui.neighbors.contains(&id) => {
+                return handle_control_error(409, "Already a neighbor of this drone")
+            }
+            Ok(id) if drone_gui.unlinked.contains(&id) => InternalCommand::Relink(id),
+            Ok(_) => {
+                return handle_control_error(
+                    404,
+                    "Only neighbors removed from the GUI can be restored",
+                )
+            }
+            Err(_) => return handle_control_error(400, "Neighbor must be a node id"),
+        },
+        ["remove-neighbor"] => match body.parse::<NodeId>() {
+            Ok(id) if drone_gui.neighbors.contains(&id) => InternalCommand::Unlink(id),
+            Ok(_) => return handle_control_error(404, "Not a neighbor of this drone"),
+            Err(_) => return handle_control_error(400, "Neighbor must be a node id"),
+        },
+        _ => return handle_not_found(),
+    };
+
+    if drone_gui.commands.send(command).is_err() {
+        return handle_control_error(410, "Drone is no longer listening");
+    }
 
-fn handle_drone(drone_gui: &DroneGUI) -> Response<Cursor<Vec<u8>>> {
-    drone_gui.drone_page()
+    Response::from_string("OK")
+        .with_header("Content-Type: text/plain".parse::<Header>().unwrap())
 }
 
-const fn script_file() -> &'static str {
-    include_str!("../assets/script.js")
+fn generate_token() -> String {
+    let mut rng = rand::thread_rng();
+    (0..16).map(|_| format!("{:02x}", rng.gen::<u8>())).collect()
 }
 
-fn handle_script() -> Response<Cursor<Vec<u8>>> {
-    Response::from_string(script_file())
-        .with_header("Content-Type: text/javascript".parse::<Header>().unwrap())
+fn handle_control_error(status: u16, message: &str) -> Response<Cursor<Vec<u8>>> {
+    Response::from_string(message)
+        .with_status_code(status)
+        .with_header("Content-Type: text/plain".parse::<Header>().unwrap())
 }
 
 fn handle_not_found() -> Response<Cursor<Vec<u8>>> {
@@ -275,6 +435,7 @@ fn wrap_html(title: &str, body: String) -> String {
     <title>{title}</title>
     <link rel="icon" href="/bagel.png"/>
     <link rel="stylesheet" href="/style"/>
+    <script src="/live-reload" defer></script>
 </head>
 <body>
     {body}
@@ -284,11 +445,23 @@ fn wrap_html(title: &str, body: String) -> String {
     )
 }
 
-fn handle_message(message: GUIMessage) {
-    let mut guis = GUIS.lock().unwrap();
+fn handle_message(message: GUIMessage, state: &GuiState) {
+    apply_message(
+        &mut state.guis.lock().unwrap(),
+        message,
+        state.drop_retention,
+    );
+}
+
+/// Updates the drone GUIs, shared by every consumer of the message feed
+fn apply_message(
+    guis: &mut HashMap<NodeId, DroneGUI>,
+    message: GUIMessage,
+    drop_retention: DropRetention,
+) {
     match message {
-        GUIMessage::DroneAdded(id, pdr) => {
-            guis.insert(id, DroneGUI::new(id, pdr));
+        GUIMessage::DroneAdded(gui) => {
+            guis.insert(gui.id, *gui);
         }
         GUIMessage::DroneRemoved(id) => {
             guis.remove(&id);
@@ -298,9 +471,30 @@ fn handle_message(message: GUIMessage) {
                 gui.set_pdr(pdr);
             }
         }
-        GUIMessage::BagelDropped(id, dropped) => {
+        GUIMessage::NeighborsChanged(id, neighbors, unlinked) => {
             if let Some(gui) = guis.get_mut(&id) {
-                gui.bagel_dropped(dropped);
+                gui.neighbors = neighbors;
+                gui.unlinked = unlinked;
+            }
+        }
+        GUIMessage::BagelDropped(id, dropped, time) => {
+            if let Some(gui) = guis.get_mut(&id) {
+                gui.bagel_dropped(dropped, time, drop_retention);
+            }
+        }
+        GUIMessage::NackSent(id, nack_type) => {
+            if let Some(gui) = guis.get_mut(&id) {
+                *gui.nacks.entry(nack_type).or_default() += 1;
+            }
+        }
+        GUIMessage::FloodHandled(id) => {
+            if let Some(gui) = guis.get_mut(&id) {
+                gui.floods += 1;
+            }
+        }
+        GUIMessage::PacketInspected(id, packet) => {
+            if let Some(gui) = guis.get_mut(&id) {
+                gui.packet_inspected(*packet);
             }
         }
     }
@@ -310,21 +504,51 @@ fn handle_message(message: GUIMessage) {
 struct Drop {
     exploded: bool,
     time: SystemTime,
+    pdr: f32,
 }
 
 #[derive(Clone)]
 pub struct DroneGUI {
     id: NodeId,
     pdr: f32,
+    explosion_style: ExplosionStyle,
     drops: VecDeque<Drop>,
+    forwarded: u64,
+    exploded: u64,
+    nacks: HashMap<&'static str, u64>,
+    floods: u64,
+    packet_queue: Receiver<Packet>,
+    packets: VecDeque<InspectedPacket>,
+    inspected: u64,
+    commands: Sender<InternalCommand>,
+    neighbors: BTreeSet<NodeId>,
+    /// Neighbors removed from the GUI, the drone keeps their channels to restore the link
+    unlinked: BTreeSet<NodeId>,
 }
 
 impl DroneGUI {
-    pub fn new(id: NodeId, pdr: f32) -> Self {
+    pub fn new(
+        id: NodeId,
+        pdr: f32,
+        explosion_style: ExplosionStyle,
+        commands: Sender<InternalCommand>,
+        packet_queue: Receiver<Packet>,
+    ) -> Self {
         DroneGUI {
             id,
             pdr,
-            drops: VecDeque::with_capacity(10),
+            explosion_style,
+            drops: VecDeque::new(),
+            forwarded: 0,
+            exploded: 0,
+            nacks: HashMap::new(),
+            floods: 0,
+            packet_queue,
+            packets: VecDeque::with_capacity(inspector::INSPECTOR_CAPACITY),
+            inspected: 0,
+            commands,
+            neighbors: BTreeSet::new(),
+            unlinked: BTreeSet::new(),
         }
     }
 
@@ -332,16 +556,59 @@ impl DroneGUI {
         self.pdr = pdr;
     }
 
-    fn bagel_dropped(&mut self, result: bool) {
-        if self.drops.len() >= 10 {
-            self.drops.pop_front();
+    #[cfg(any(feature = "gui", feature = "tui"))]
+    fn neighbor_list(&self) -> String {
+        self.neighbors
+            .iter()
+            .map(|id| id.to_string())
+            .collect::<Vec<String>>()
+            .join(", ")
+    }
+
+    /// The window of [`DropRetention::Window`] ends at the latest drop, as timed by the drone
+    fn bagel_dropped(&mut self, result: bool, now: SystemTime, retention: DropRetention) {
+        if result {
+            self.exploded += 1;
+        } else {
+            self.forwarded += 1;
         }
         self.drops.push_back(Drop {
             exploded: result,
-            time: SystemTime::now(),
+            time: now,
+            pdr: self.pdr,
         });
+
+        match retention {
+            DropRetention::Count(count) => {
+                while self.drops.len() > count {
+                    self.drops.pop_front();
+                }
+            }
+            DropRetention::Window(window) => {
+                while self
+                    .drops
+                    .front()
+                    .is_some_and(|drop| drop.time + window < now)
+                {
+                    self.drops.pop_front();
+                }
+                while self.drops.len() > MAX_WINDOW_DROPS {
+                    self.drops.pop_front();
+                }
+            }
+        }
+    }
+
+    fn packet_inspected(&mut self, mut packet: InspectedPacket) {
+        self.inspected += 1;
+        packet.set_seq(self.inspected);
+        if self.packets.len() >= inspector::INSPECTOR_CAPACITY {
+            self.packets.pop_front();
+        }
+        self.packets.push_back(packet);
     }
 
+    #[cfg(feature = "gui")]
     fn anchor(&self) -> String {
         format!(
             "<a class=\"drone-link\" href=\"{}\">Bagel Bomber {}</a>",
@@ -350,21 +617,56 @@ impl DroneGUI {
         )
     }
 
+    #[cfg(feature = "gui")]
     fn url(&self) -> String {
         format!("/{}", self.id)
     }
 
-    fn drone_page(&self) -> Response<Cursor<Vec<u8>>> {
+    #[cfg(feature = "gui")]
+    fn drone_page(&self, web_socket_port: u16) -> Response<Cursor<Vec<u8>>> {
         let html_body = format!(
             r#"
 <h1>Bagel Bomber {}</h1>
 <div class="container">
-    <div id="field" data-id="{}" data-pdr="{}"></div>
+    <div id="field" data-id="{}" data-pdr="{}" data-explosion-style="{}" data-web-socket-port="{}"></div>
+    <canvas id="chart" width="500" height="200"></canvas>
+    <div class="controls">
+        <p>PDR: <span id="pdr-value">{}</span> &middot; Neighbors: <span id="neighbors">{}</span></p>
+        <input id="token" type="password" placeholder="Control token"/>
+        <div class="control-row">
+            <input id="pdr-input" type="number" min="0" max="1" step="0.01" value="{}"/>
+            <button id="set-pdr">Set PDR</button>
+        </div>
+        <div class="control-row">
+            <input id="neighbor-input" type="number" min="0" max="255" placeholder="Node id"/>
+            <button id="restore-neighbor" title="Links removed here can be restored, new links come from the controller">Restore neighbor</button>
+            <button id="remove-neighbor">Remove neighbor</button>
+        </div>
+        <div class="control-row">
+            <select id="explosion-style-input">
+                <option value="classic">Classic BOOM</option>
+                <option value="confetti-flour-burst">Confetti Flour Burst&trade;</option>
+                <option value="bagel-pocalypse">Bagel-Pocalypse</option>
+            </select>
+            <button id="set-explosion-style">Set explosion style</button>
+        </div>
+        <button id="crash" class="danger">Crash</button>
+        <p id="control-status"></p>
+    </div>
+    <a class="back-button" href="{}/inspector">Packet Inspector</a>
     <a class="back-button" href="/">Back to Hub</a>
 </div>
 <script src="/script" defer></script>
 "#,
-            self.id, self.id, self.pdr
+            self.id,
+            self.id,
+            self.pdr,
+            self.explosion_style.name(),
+            web_socket_port,
+            self.pdr,
+            self.neighbor_list(),
+            self.pdr,
+            self.url()
         );
         Response::from_string(wrap_html(&format!("Bagel Bomber {}", self.id), html_body))
             .with_header("Content-Type: text/html".parse::<Header>().unwrap())
@@ -0,0 +1,195 @@
+use super::{handle_not_found, DroneGUI, Drop, ExplosionStyle, InspectedPacket};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::io::Cursor;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tiny_http::{Header, Response};
+use wg_2024::network::NodeId;
+
+#[derive(Serialize)]
+struct DroneSummary {
+    id: NodeId,
+    pdr: f32,
+    explosion_style: ExplosionStyle,
+}
+
+#[derive(Serialize)]
+struct DroneState {
+    id: NodeId,
+    pdr: f32,
+    explosion_style: ExplosionStyle,
+    neighbors: Vec<NodeId>,
+    forwarded: u64,
+    exploded: u64,
+    drops: Vec<DropRecord>,
+}
+
+/// Drops aggregated over one second, `time` is the start of the second
+#[derive(Serialize)]
+struct HistoryBucket {
+    time: u64,
+    forwarded: u64,
+    exploded: u64,
+    pdr: f32,
+}
+
+/// A drop as exposed by the API, `time` is in seconds since the unix epoch
+#[derive(Serialize)]
+struct DropRecord {
+    exploded: bool,
+    time: f64,
+}
+
+impl From<&Drop> for DropRecord {
+    fn from(drop: &Drop) -> Self {
+        DropRecord {
+            exploded: drop.exploded,
+            time: unix_seconds(drop.time),
+        }
+    }
+}
+
+impl From<&DroneGUI> for DroneState {
+    fn from(gui: &DroneGUI) -> Self {
+        let neighbors = gui.neighbors.iter().copied().collect::<Vec<NodeId>>();
+        DroneState {
+            id: gui.id,
+            pdr: gui.pdr,
+            explosion_style: gui.explosion_style,
+            neighbors,
+            forwarded: gui.forwarded,
+            exploded: gui.exploded,
+            drops: gui.drops.iter().map(DropRecord::from).collect(),
+        }
+    }
+}
+
+/// Routes `/api/drones`, `/api/drones/{id}`, `/api/drones/{id}/drops?since=`
+/// `/api/drones/{id}/history` and `/api/drones/{id}/packets?after=`
+pub(super) fn handle_api(
+    path: &str,
+    query: &str,
+    guis: &HashMap<NodeId, DroneGUI>,
+) -> Response<Cursor<Vec<u8>>> {
+    let segments = path.trim_matches('/').split('/').collect::<Vec<&str>>();
+
+    match segments[..] {
+        ["api", "drones"] => {
+            let mut drones = guis
+                .values()
+                .map(|gui| DroneSummary {
+                    id: gui.id,
+                    pdr: gui.pdr,
+                    explosion_style: gui.explosion_style,
+                })
+                .collect::<Vec<DroneSummary>>();
+            drones.sort_by_key(|drone| drone.id);
+            json_response(&drones)
+        }
+        ["api", "drones", id] => match find_gui(guis, id) {
+            Some(gui) => json_response(&DroneState::from(gui)),
+            None => handle_api_error(404, "Unknown drone"),
+        },
+        ["api", "drones", id, "drops"] => {
+            let gui = match find_gui(guis, id) {
+                Some(gui) => gui,
+                None => return handle_api_error(404, "Unknown drone"),
+            };
+            let since = match query_param(query, "since").map(str::parse::<f64>) {
+                None => f64::NEG_INFINITY,
+                Some(Ok(since)) => since,
+                Some(Err(_)) => {
+                    return handle_api_error(400, "`since` must be a unix timestamp in seconds")
+                }
+            };
+            let drops = gui
+                .drops
+                .iter()
+                .map(DropRecord::from)
+                .filter(|drop| drop.time > since)
+                .collect::<Vec<DropRecord>>();
+            json_response(&drops)
+        }
+        ["api", "drones", id, "history"] => match find_gui(guis, id) {
+            Some(gui) => json_response(&history(gui)),
+            None => handle_api_error(404, "Unknown drone"),
+        },
+        ["api", "drones", id, "packets"] => {
+            let gui = match find_gui(guis, id) {
+                Some(gui) => gui,
+                None => return handle_api_error(404, "Unknown drone"),
+            };
+            let after = match query_param(query, "after").map(str::parse::<u64>) {
+                None => 0,
+                Some(Ok(after)) => after,
+                Some(Err(_)) => return handle_api_error(400, "`after` must be a sequence number"),
+            };
+            let packets = gui
+                .packets
+                .iter()
+                .filter(|packet| packet.seq() > after)
+                .collect::<Vec<&InspectedPacket>>();
+            json_response(&packets)
+        }
+        _ => handle_not_found(),
+    }
+}
+
+/// Buckets the retained drops by second; the PDR of a bucket is the one of its last drop
+fn history(gui: &DroneGUI) -> Vec<HistoryBucket> {
+    let mut buckets: Vec<HistoryBucket> = Vec::new();
+    for drop in gui.drops.iter() {
+        let time = unix_seconds(drop.time) as u64;
+        let bucket = match buckets.last_mut() {
+            Some(bucket) if bucket.time == time => bucket,
+            _ => {
+                buckets.push(HistoryBucket {
+                    time,
+                    forwarded: 0,
+                    exploded: 0,
+                    pdr: drop.pdr,
+                });
+                buckets.last_mut().unwrap()
+            }
+        };
+        if drop.exploded {
+            bucket.exploded += 1;
+        } else {
+            bucket.forwarded += 1;
+        }
+        bucket.pdr = drop.pdr;
+    }
+    buckets
+}
+
+fn find_gui<'a>(guis: &'a HashMap<NodeId, DroneGUI>, id: &str) -> Option<&'a DroneGUI> {
+    id.parse::<NodeId>().ok().and_then(|id| guis.get(&id))
+}
+
+pub(super) fn query_param<'a>(query: &'a str, name: &str) -> Option<&'a str> {
+    query
+        .split('&')
+        .filter_map(|pair| pair.split_once('='))
+        .find(|(key, _)| *key == name)
+        .map(|(_, value)| value)
+}
+
+pub(super) fn unix_seconds(time: SystemTime) -> f64 {
+    time.duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs_f64())
+        .unwrap_or(0.0)
+}
+
+fn json_response<T: Serialize>(value: &T) -> Response<Cursor<Vec<u8>>> {
+    match serde_json::to_string(value) {
+        Ok(json) => Response::from_string(json)
+            .with_header("Content-Type: application/json".parse::<Header>().unwrap()),
+        Err(_) => handle_api_error(500, "Could not serialize drone state"),
+    }
+}
+
+fn handle_api_error(status: u16, message: &str) -> Response<Cursor<Vec<u8>>> {
+    Response::from_string(format!("{{ \"error\": \"{}\" }}", message))
+        .with_status_code(status)
+        .with_header("Content-Type: application/json".parse::<Header>().unwrap())
+}
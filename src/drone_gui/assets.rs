@@ -0,0 +1,142 @@
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::io::Cursor;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+use tiny_http::{Header, Response};
+
+/// A file the pages need, compiled in and overridable from the asset directory
+pub(super) struct Asset {
+    file_name: &'static str,
+    content_type: &'static str,
+    embedded: &'static [u8],
+}
+
+pub(super) const STYLE: Asset = Asset {
+    file_name: "style.css",
+    content_type: "text/css",
+    embedded: include_bytes!("../../assets/style.css"),
+};
+
+pub(super) const SCRIPT: Asset = Asset {
+    file_name: "script.js",
+    content_type: "text/javascript",
+    embedded: include_bytes!("../../assets/script.js"),
+};
+
+pub(super) const ICON: Asset = Asset {
+    file_name: "bagel.png",
+    content_type: "image/png",
+    embedded: include_bytes!("../../assets/bagel.png"),
+};
+
+pub(super) const INSPECTOR_SCRIPT: Asset = Asset {
+    file_name: "inspector.js",
+    content_type: "text/javascript",
+    embedded: include_bytes!("../../assets/inspector.js"),
+};
+
+pub(super) const PLAYBACK_SCRIPT: Asset = Asset {
+    file_name: "playback.js",
+    content_type: "text/javascript",
+    embedded: include_bytes!("../../assets/playback.js"),
+};
+
+pub(super) const OVERVIEW_SCRIPT: Asset = Asset {
+    file_name: "overview.js",
+    content_type: "text/javascript",
+    embedded: include_bytes!("../../assets/overview.js"),
+};
+
+const LIVE_RELOAD_SCRIPT: &str = include_str!("../../assets/live-reload.js");
+
+const ASSETS: [&Asset; 6] = [
+    &STYLE,
+    &SCRIPT,
+    &ICON,
+    &INSPECTOR_SCRIPT,
+    &PLAYBACK_SCRIPT,
+    &OVERVIEW_SCRIPT,
+];
+
+/// Where the pages get their assets from
+pub(super) struct Assets {
+    dir: Option<PathBuf>,
+    live_reload: bool,
+    /// The overrides found in the directory at startup, unused when reloading live
+    overrides: HashMap<&'static str, Vec<u8>>,
+}
+
+impl Assets {
+    pub(super) fn new(dir: Option<PathBuf>, live_reload: bool) -> Assets {
+        let overrides = match &dir {
+            Some(dir) if !live_reload => ASSETS
+                .iter()
+                .filter_map(|asset| {
+                    std::fs::read(dir.join(asset.file_name))
+                        .ok()
+                        .map(|content| (asset.file_name, content))
+                })
+                .collect(),
+            _ => HashMap::new(),
+        };
+
+        Assets {
+            dir,
+            live_reload,
+            overrides,
+        }
+    }
+
+    fn content(&self, asset: &Asset) -> Cow<'_, [u8]> {
+        if let Some(content) = self.overrides.get(asset.file_name) {
+            return Cow::Borrowed(content);
+        }
+        match &self.dir {
+            Some(dir) if self.live_reload => std::fs::read(dir.join(asset.file_name))
+                .map(Cow::Owned)
+                .unwrap_or(Cow::Borrowed(asset.embedded)),
+            _ => Cow::Borrowed(asset.embedded),
+        }
+    }
+
+    pub(super) fn serve(&self, asset: &Asset) -> Response<Cursor<Vec<u8>>> {
+        Response::from_data(self.content(asset).into_owned()).with_header(
+            format!("Content-Type: {}", asset.content_type)
+                .parse::<Header>()
+                .unwrap(),
+        )
+    }
+
+    /// Pages always load this script, it only does something in development mode
+    pub(super) fn serve_live_reload(&self) -> Response<Cursor<Vec<u8>>> {
+        let script = if self.live_reload {
+            LIVE_RELOAD_SCRIPT
+        } else {
+            ""
+        };
+        Response::from_string(script)
+            .with_header("Content-Type: text/javascript".parse::<Header>().unwrap())
+    }
+
+    /// Changes whenever a file in the asset directory does
+    pub(super) fn serve_version(&self) -> Response<Cursor<Vec<u8>>> {
+        let version = match &self.dir {
+            Some(dir) if self.live_reload => latest_modification(dir),
+            _ => 0,
+        };
+        Response::from_string(version.to_string())
+            .with_header("Content-Type: text/plain".parse::<Header>().unwrap())
+    }
+}
+
+fn latest_modification(dir: &Path) -> u128 {
+    ASSETS
+        .iter()
+        .filter_map(|asset| std::fs::metadata(dir.join(asset.file_name)).ok())
+        .filter_map(|metadata| metadata.modified().ok())
+        .filter_map(|modified| modified.duration_since(SystemTime::UNIX_EPOCH).ok())
+        .map(|modified| modified.as_nanos())
+        .max()
+        .unwrap_or(0)
+}
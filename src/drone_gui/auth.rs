@@ -0,0 +1,131 @@
+use super::server::GuiState;
+use super::wrap_html;
+use std::io::Cursor;
+use tiny_http::{Header, Request, Response};
+
+/// Cookie the browser keeps the read token in, after it was given once in the URL
+const TOKEN_COOKIE: &str = "bagel_token";
+
+/// What a request is allowed to do
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub(super) enum Scope {
+    Denied,
+    /// Pages, API, metrics and the WebSocket
+    Read,
+    /// Everything, including the control endpoints
+    Control,
+}
+
+/// The credentials a request carries, wherever they come from
+pub(super) struct Credentials<'a> {
+    pub(super) authorization: Option<&'a str>,
+    pub(super) cookie: Option<&'a str>,
+    pub(super) query: &'a str,
+}
+
+impl<'a> Credentials<'a> {
+    pub(super) fn from_request(request: &'a Request, query: &'a str) -> Self {
+        let header = |name: &'static str| {
+            request
+                .headers()
+                .iter()
+                .find(|header| header.field.equiv(name))
+                .map(|header| header.value.as_str())
+        };
+        Credentials {
+            authorization: header("Authorization"),
+            cookie: header("Cookie"),
+            query,
+        }
+    }
+
+    /// The control scope is only granted through the `Authorization` header,
+    /// so that a page on another site can't use the cookie to send commands
+    pub(super) fn scope(&self, state: &GuiState) -> Scope {
+        let bearer = self
+            .authorization
+            .and_then(|value| value.strip_prefix("Bearer "));
+        if bearer.is_some_and(|token| tokens_match(token, &state.control_token)) {
+            return Scope::Control;
+        }
+
+        let read_token = match &state.read_token {
+            Some(read_token) => read_token,
+            None => return Scope::Read,
+        };
+        let granted = bearer
+            .into_iter()
+            .chain(self.query_token())
+            .chain(self.cookie_token())
+            .any(|token| {
+                tokens_match(token, read_token) || tokens_match(token, &state.control_token)
+            });
+
+        if granted {
+            Scope::Read
+        } else {
+            Scope::Denied
+        }
+    }
+
+    /// The token given in the URL, which should be remembered in a cookie
+    pub(super) fn query_token(&self) -> Option<&'a str> {
+        self.query
+            .split('&')
+            .find_map(|pair| pair.strip_prefix("token="))
+    }
+
+    fn cookie_token(&self) -> Option<&'a str> {
+        self.cookie?.split(';').find_map(|cookie| {
+            cookie
+                .trim()
+                .strip_prefix(TOKEN_COOKIE)
+                .and_then(|rest| rest.strip_prefix('='))
+        })
+    }
+}
+
+/// Compares every byte, so the time taken doesn't tell how much of the token was right
+fn tokens_match(given: &str, expected: &str) -> bool {
+    given.len() == expected.len()
+        && given
+            .bytes()
+            .zip(expected.bytes())
+            .fold(0, |difference, (a, b)| difference | (a ^ b))
+            == 0
+}
+
+/// The cookie remembering the read token given in the URL.
+///
+/// Only the configured read token is remembered, never the control token, which must not be
+/// sent along by the browser
+pub(super) fn token_cookie(credentials: &Credentials, state: &GuiState) -> Option<Header> {
+    let read_token = state.read_token.as_ref()?;
+    let token = credentials.query_token()?;
+    if !tokens_match(token, read_token)
+        || tokens_match(token, &state.control_token)
+        || !token.bytes().all(is_cookie_octet)
+    {
+        return None;
+    }
+    format!(
+        "Set-Cookie: {}={}; Path=/; HttpOnly; SameSite=Strict",
+        TOKEN_COOKIE, token
+    )
+    .parse::<Header>()
+    .ok()
+}
+
+/// The characters RFC 6265 allows in a cookie value, anything else could add attributes
+fn is_cookie_octet(byte: u8) -> bool {
+    matches!(byte, 0x21 | 0x23..=0x2B | 0x2D..=0x3A | 0x3C..=0x5B | 0x5D..=0x7E)
+}
+
+pub(super) fn handle_unauthorized() -> Response<Cursor<Vec<u8>>> {
+    let html_body = "<h1>Unauthorized</h1>\
+        <p>Open the link printed when the GUI started, or add <code>?token=</code> to the URL.</p>"
+        .to_string();
+    Response::from_string(wrap_html("Unauthorized", html_body))
+        .with_status_code(401)
+        .with_header("Content-Type: text/html".parse::<Header>().unwrap())
+}
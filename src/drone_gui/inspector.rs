@@ -0,0 +1,110 @@
+use super::api::unix_seconds;
+#[cfg(feature = "gui")]
+use super::{wrap_html, DroneGUI};
+use serde::Serialize;
+#[cfg(feature = "gui")]
+use std::collections::HashMap;
+#[cfg(feature = "gui")]
+use std::io::Cursor;
+use std::time::SystemTime;
+#[cfg(feature = "gui")]
+use tiny_http::{Header, Response};
+use wg_2024::network::NodeId;
+use wg_2024::packet::{Packet, PacketType};
+
+/// How many packets each drone GUI keeps for the inspector
+pub(super) const INSPECTOR_CAPACITY: usize = 200;
+
+/// A packet as seen by a drone, together with what the drone decided to do with it
+#[derive(Clone, Debug, Serialize)]
+pub struct InspectedPacket {
+    seq: u64,
+    time: f64,
+    packet_type: &'static str,
+    session_id: u64,
+    fragment_index: Option<u64>,
+    hops: Vec<NodeId>,
+    hop_index: usize,
+    decision: String,
+}
+
+impl InspectedPacket {
+    pub fn new(packet: &Packet, decision: String, time: SystemTime) -> Self {
+        let (packet_type, fragment_index) = match &packet.pack_type {
+            PacketType::MsgFragment(fragment) => ("MsgFragment", Some(fragment.fragment_index)),
+            PacketType::Ack(ack) => ("Ack", Some(ack.fragment_index)),
+            PacketType::Nack(nack) => ("Nack", Some(nack.fragment_index)),
+            PacketType::FloodRequest(_) => ("FloodRequest", None),
+            PacketType::FloodResponse(_) => ("FloodResponse", None),
+        };
+        InspectedPacket {
+            seq: 0,
+            time: unix_seconds(time),
+            packet_type,
+            session_id: packet.session_id,
+            fragment_index,
+            hops: packet.routing_header.hops.clone(),
+            hop_index: packet.routing_header.hop_index,
+            decision,
+        }
+    }
+
+    pub(super) fn seq(&self) -> u64 {
+        self.seq
+    }
+
+    pub(super) fn set_seq(&mut self, seq: u64) {
+        self.seq = seq;
+    }
+}
+
+#[cfg(feature = "gui")]
+pub(super) fn inspected_drone<'a>(
+    path: &str,
+    guis: &'a HashMap<NodeId, DroneGUI>,
+) -> Option<&'a DroneGUI> {
+    path.strip_prefix('/')?
+        .strip_suffix("/inspector")?
+        .parse::<NodeId>()
+        .ok()
+        .and_then(|id| guis.get(&id))
+}
+
+#[cfg(feature = "gui")]
+pub(super) fn handle_inspector(drone_gui: &DroneGUI) -> Response<Cursor<Vec<u8>>> {
+    let html_body = format!(
+        r#"
+<h1>Bagel Bomber {} Inspector</h1>
+<div class="container">
+    <div class="control-row">
+        <input id="session-filter" type="text" placeholder="Session id"/>
+        <select id="type-filter">
+            <option value="">All packets</option>
+            <option>MsgFragment</option>
+            <option>Ack</option>
+            <option>Nack</option>
+            <option>FloodRequest</option>
+            <option>FloodResponse</option>
+        </select>
+    </div>
+    <table id="packets" data-id="{}">
+        <thead>
+            <tr><th>#</th><th>Type</th><th>Session</th><th>Fragment</th><th>Route</th><th>Decision</th></tr>
+        </thead>
+        <tbody></tbody>
+    </table>
+    <a class="back-button" href="{}">Back to Bagel Bomber {}</a>
+</div>
+<script src="/inspector-script" defer></script>
+"#,
+        drone_gui.id,
+        drone_gui.id,
+        drone_gui.url(),
+        drone_gui.id
+    );
+    Response::from_string(wrap_html(
+        &format!("Bagel Bomber {} Inspector", drone_gui.id),
+        html_body,
+    ))
+    .with_header("Content-Type: text/html".parse::<Header>().unwrap())
+}
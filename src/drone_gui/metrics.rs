@@ -0,0 +1,121 @@
+use super::DroneGUI;
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::io::Cursor;
+use tiny_http::{Header, Response};
+use wg_2024::network::NodeId;
+use wg_2024::packet::NackType;
+
+const NACK_LABELS: [&str; 4] = [
+    "error_in_routing",
+    "destination_is_drone",
+    "dropped",
+    "unexpected_recipient",
+];
+
+pub(super) fn nack_label(nack: &NackType) -> &'static str {
+    match nack {
+        NackType::ErrorInRouting(_) => NACK_LABELS[0],
+        NackType::DestinationIsDrone => NACK_LABELS[1],
+        NackType::Dropped => NACK_LABELS[2],
+        NackType::UnexpectedRecipient(_) => NACK_LABELS[3],
+    }
+}
+
+/// Serves every registered drone in the Prometheus text exposition format
+pub(super) fn handle_metrics(guis: &HashMap<NodeId, DroneGUI>) -> Response<Cursor<Vec<u8>>> {
+    let mut guis = guis.values().collect::<Vec<&DroneGUI>>();
+    guis.sort_by_key(|gui| gui.id);
+
+    let mut body = String::new();
+
+    write_family(
+        &mut body,
+        "bagel_bomber_fragments_forwarded_total",
+        "counter",
+        "Fragments forwarded to the next hop",
+        &guis,
+        |gui| gui.forwarded as f64,
+    );
+    write_family(
+        &mut body,
+        "bagel_bomber_fragments_dropped_total",
+        "counter",
+        "Fragments dropped because of the packet drop rate",
+        &guis,
+        |gui| gui.exploded as f64,
+    );
+
+    writeln!(
+        body,
+        "# HELP bagel_bomber_nacks_total Nacks sent back, by nack type"
+    )
+    .ok();
+    writeln!(body, "# TYPE bagel_bomber_nacks_total counter").ok();
+    for gui in guis.iter() {
+        for label in NACK_LABELS {
+            writeln!(
+                body,
+                "bagel_bomber_nacks_total{{drone=\"{}\",type=\"{}\"}} {}",
+                gui.id,
+                label,
+                gui.nacks.get(label).copied().unwrap_or(0)
+            )
+            .ok();
+        }
+    }
+
+    write_family(
+        &mut body,
+        "bagel_bomber_floods_total",
+        "counter",
+        "Flood requests handled",
+        &guis,
+        |gui| gui.floods as f64,
+    );
+    write_family(
+        &mut body,
+        "bagel_bomber_pdr",
+        "gauge",
+        "Current packet drop rate",
+        &guis,
+        |gui| gui.pdr as f64,
+    );
+    write_family(
+        &mut body,
+        "bagel_bomber_queue_depth",
+        "gauge",
+        "Packets waiting in the drone's receiving channel",
+        &guis,
+        |gui| gui.packet_queue.len() as f64,
+    );
+    write_family(
+        &mut body,
+        "bagel_bomber_neighbors",
+        "gauge",
+        "Number of neighbors the drone can send to",
+        &guis,
+        |gui| gui.neighbors.len() as f64,
+    );
+
+    Response::from_string(body).with_header(
+        "Content-Type: text/plain; version=0.0.4"
+            .parse::<Header>()
+            .unwrap(),
+    )
+}
+
+fn write_family(
+    body: &mut String,
+    name: &str,
+    metric_type: &str,
+    help: &str,
+    guis: &[&DroneGUI],
+    value: impl Fn(&DroneGUI) -> f64,
+) {
+    writeln!(body, "# HELP {} {}", name, help).ok();
+    writeln!(body, "# TYPE {} {}", name, metric_type).ok();
+    for gui in guis {
+        writeln!(body, "{}{{drone=\"{}\"}} {}", name, gui.id, value(gui)).ok();
+    }
+}
@@ -0,0 +1,26 @@
+use super::wrap_html;
+use std::io::Cursor;
+use tiny_http::{Header, Response};
+
+pub(super) fn handle_overview(web_socket_port: u16) -> Response<Cursor<Vec<u8>>> {
+    let html_body = format!(
+        r#"
+<h1>Mission Control</h1>
+<div class="container">
+    <div class="playback-controls">
+        <label for="sort">Sort by</label>
+        <select id="sort">
+            <option value="id">Drone id</option>
+            <option value="drop-rate">Drop rate</option>
+        </select>
+    </div>
+    <div id="tiles" class="tiles" data-web-socket-port="{}"></div>
+    <a class="back-button" href="/">Back to Hub</a>
+</div>
+<script src="/overview-script" defer></script>
+"#,
+        web_socket_port
+    );
+    Response::from_string(wrap_html("Mission Control", html_body))
+        .with_header("Content-Type: text/html".parse::<Header>().unwrap())
+}
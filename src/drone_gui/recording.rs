@@ -0,0 +1,210 @@
+use super::server::GuiHandle;
+#[cfg(feature = "gui")]
+use super::wrap_html;
+use super::GUIMessage;
+use crate::clock::{self, Clock};
+use crossbeam_channel::{bounded, select, unbounded, Receiver, Sender};
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+#[cfg(feature = "gui")]
+use std::io::Cursor;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::SystemTime;
+#[cfg(feature = "gui")]
+use tiny_http::{Header, Response};
+use wg_2024::network::NodeId;
+
+/// The part of a [`GUIMessage`] worth replaying
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(tag = "type")]
+enum RecordedEvent {
+    DroneAdded { id: NodeId, pdr: f32 },
+    DroneRemoved { id: NodeId },
+    PDRChanged { id: NodeId, pdr: f32 },
+    BagelDropped { id: NodeId, exploded: bool },
+}
+
+impl RecordedEvent {
+    /// The event and the time it happened, if the drone timed it
+    fn from_message(message: &GUIMessage) -> Option<(Self, Option<SystemTime>)> {
+        match message {
+            GUIMessage::DroneAdded(gui) => Some((
+                RecordedEvent::DroneAdded {
+                    id: gui.id,
+                    pdr: gui.pdr,
+                },
+                None,
+            )),
+            GUIMessage::DroneRemoved(id) => Some((RecordedEvent::DroneRemoved { id: *id }, None)),
+            GUIMessage::PDRChanged(id, pdr) => {
+                Some((RecordedEvent::PDRChanged { id: *id, pdr: *pdr }, None))
+            }
+            GUIMessage::BagelDropped(id, exploded, time) => Some((
+                RecordedEvent::BagelDropped {
+                    id: *id,
+                    exploded: *exploded,
+                },
+                Some(*time),
+            )),
+            _ => None,
+        }
+    }
+}
+
+/// One line of a recording, `time` is in seconds since the recording started
+#[derive(Clone, Debug, Serialize, Deserialize)]
+struct RecordedMessage {
+    time: f64,
+    event: RecordedEvent,
+}
+
+/// Writes the messages of the drones it is handed to as JSON lines, so that a headless
+/// run can be watched later with [`super::GuiServer::playback`]
+pub struct GuiRecorder {
+    sender: Sender<GUIMessage>,
+    shutdown: Option<Sender<()>>,
+    handle: Option<JoinHandle<std::io::Result<()>>>,
+}
+
+impl GuiRecorder {
+    pub fn create(path: impl AsRef<Path>) -> std::io::Result<GuiRecorder> {
+        GuiRecorder::create_with_clock(path, clock::system_clock())
+    }
+
+    /// Times the recording with the given clock, which should be the drones' one: drops are
+    /// recorded when the drones say they happened, everything else when it arrives
+    pub fn create_with_clock(
+        path: impl AsRef<Path>,
+        clock: Arc<dyn Clock>,
+    ) -> std::io::Result<GuiRecorder> {
+        let file = BufWriter::new(File::create(path)?);
+        let (sender, receiver) = unbounded();
+        let (shutdown_send, shutdown_recv) = bounded(0);
+        let start = clock.now();
+        let handle = thread::spawn(move || {
+            recorder_daemon(file, receiver, shutdown_recv, start, clock.as_ref())
+        });
+
+        Ok(GuiRecorder {
+            sender,
+            shutdown: Some(shutdown_send),
+            handle: Some(handle),
+        })
+    }
+
+    /// A handle to give to the drones that should be recorded
+    pub fn handle(&self) -> GuiHandle {
+        GuiHandle::new(self.sender.clone())
+    }
+
+    /// Writes what is left and closes the file
+    pub fn finish(mut self) -> std::io::Result<()> {
+        self.stop()
+    }
+
+    fn stop(&mut self) -> std::io::Result<()> {
+        self.shutdown.take();
+        match self.handle.take() {
+            Some(handle) => handle.join().unwrap_or(Ok(())),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Drop for GuiRecorder {
+    fn drop(&mut self) {
+        self.stop().ok();
+    }
+}
+
+fn recorder_daemon(
+    mut file: BufWriter<File>,
+    receiver: Receiver<GUIMessage>,
+    shutdown: Receiver<()>,
+    start: SystemTime,
+    clock: &dyn Clock,
+) -> std::io::Result<()> {
+    loop {
+        select! {
+            recv(receiver) -> message => match message {
+                Ok(message) => record(&mut file, &message, start, clock)?,
+                Err(_) => break,
+            },
+            recv(shutdown) -> _ => {
+                for message in receiver.try_iter() {
+                    record(&mut file, &message, start, clock)?;
+                }
+                break;
+            }
+        }
+    }
+    file.flush()
+}
+
+fn record(
+    file: &mut BufWriter<File>,
+    message: &GUIMessage,
+    start: SystemTime,
+    clock: &dyn Clock,
+) -> std::io::Result<()> {
+    if let Some((event, time)) = RecordedEvent::from_message(message) {
+        let time = time.unwrap_or_else(|| clock.now());
+        let line = RecordedMessage {
+            time: time.duration_since(start).unwrap_or_default().as_secs_f64(),
+            event,
+        };
+        writeln!(file, "{}", serde_json::to_string(&line)?)?;
+    }
+    Ok(())
+}
+
+/// Reads a recording and turns it into the JSON array served to the playback page
+#[cfg(feature = "gui")]
+pub(super) fn load(path: &Path) -> Result<String, String> {
+    let content = std::fs::read_to_string(path).map_err(|err| err.to_string())?;
+    let messages = content
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(index, line)| {
+            serde_json::from_str::<RecordedMessage>(line)
+                .map_err(|err| format!("line {}: {}", index + 1, err))
+        })
+        .collect::<Result<Vec<RecordedMessage>, String>>()?;
+    serde_json::to_string(&messages).map_err(|err| err.to_string())
+}
+
+#[cfg(feature = "gui")]
+pub(super) fn handle_playback() -> Response<Cursor<Vec<u8>>> {
+    let html_body = r#"
+<h1>Bagel Bomber Playback</h1>
+<div class="container">
+    <div class="playback-controls">
+        <button id="play">Play</button>
+        <input id="timeline" type="range" min="0" max="0" step="0.01" value="0"/>
+        <span id="time">0.0s</span>
+        <select id="speed">
+            <option value="0.5">0.5x</option>
+            <option value="1" selected>1x</option>
+            <option value="2">2x</option>
+            <option value="5">5x</option>
+            <option value="10">10x</option>
+        </select>
+    </div>
+    <div id="tiles" class="tiles"></div>
+</div>
+<script src="/playback-script" defer></script>
+"#
+    .to_string();
+    Response::from_string(wrap_html("Bagel Bomber Playback", html_body))
+        .with_header("Content-Type: text/html".parse::<Header>().unwrap())
+}
+
+#[cfg(feature = "gui")]
+pub(super) fn handle_recording(recording: &str) -> Response<Cursor<Vec<u8>>> {
+    Response::from_string(recording)
+        .with_header("Content-Type: application/json".parse::<Header>().unwrap())
+}
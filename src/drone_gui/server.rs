@@ -0,0 +1,407 @@
+#[cfg(feature = "gui")]
+use super::assets::Assets;
+#[cfg(feature = "gui")]
+use super::web_socket::web_socket_daemon;
+use super::{
+    generate_token, handle_http_request, handle_message, DroneGUI, DropRetention, GUIMessage,
+};
+use crate::clock::{self, Clock};
+use crossbeam_channel::{bounded, select, unbounded, Receiver, Sender};
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+use std::io::ErrorKind;
+use std::net::TcpListener;
+#[cfg(feature = "gui")]
+use std::net::TcpStream;
+#[cfg(feature = "gui")]
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, Weak};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+use tiny_http::Server;
+use wg_2024::network::NodeId;
+
+const CONTROL_TOKEN_VAR: &str = "BAGEL_BOMBER_GUI_TOKEN";
+const READ_TOKEN_VAR: &str = "BAGEL_BOMBER_GUI_READ_TOKEN";
+const ASSET_DIR_VAR: &str = "BAGEL_BOMBER_GUI_ASSETS";
+/// Threads answering HTTP requests, so that a slow client doesn't hold up the others
+const HTTP_WORKERS: usize = 4;
+/// How long an HTTP worker waits after the server failed to receive a request
+const HTTP_ERROR_BACKOFF: Duration = Duration::from_millis(100);
+
+/// Settings of a [`GuiServer`]
+#[derive(Clone, Debug)]
+pub struct GuiConfig {
+    /// Port of the pages, the API and the metrics, 0 picks a free one
+    pub http_port: u16,
+    /// Port the drone pages stream their drops from, 0 picks a free one
+    pub web_socket_port: u16,
+    /// How many drops each drone remembers, by default those of the last five minutes up to
+    /// [`super::MAX_WINDOW_DROPS`]
+    pub drop_retention: DropRetention,
+    /// Token required by the control endpoints, generated at startup if missing.
+    /// Defaults to the `BAGEL_BOMBER_GUI_TOKEN` environment variable
+    pub control_token: Option<String>,
+    /// Token required to see the pages, the API, the metrics and the WebSocket.
+    /// Defaults to the `BAGEL_BOMBER_GUI_READ_TOKEN` environment variable
+    pub read_token: Option<String>,
+    /// Generates a read token at startup if none is given, instead of leaving the GUI open
+    pub require_read_token: bool,
+    /// Directory with replacements for `style.css`, `script.js`, `bagel.png`, `inspector.js`,
+    /// `playback.js` and `overview.js`, missing files fall back to the embedded ones.
+    /// Defaults to the `BAGEL_BOMBER_GUI_ASSETS` environment variable
+    pub asset_dir: Option<PathBuf>,
+    /// Reads the asset directory on every request and reloads the pages when it changes
+    pub live_reload: bool,
+    /// Decides which drops the WebSocket streams as new, it should be the drones' clock
+    pub clock: Arc<dyn Clock>,
+    /// Stops the server once the last of the drones it has shown is removed, instead of
+    /// waiting for [`GuiServer::shutdown`]
+    pub shutdown_when_empty: bool,
+}
+
+impl Default for GuiConfig {
+    fn default() -> Self {
+        GuiConfig {
+            http_port: 8463,
+            web_socket_port: 8464,
+            drop_retention: DropRetention::Window(Duration::from_secs(300)),
+            control_token: std::env::var(CONTROL_TOKEN_VAR).ok(),
+            read_token: std::env::var(READ_TOKEN_VAR).ok(),
+            require_read_token: false,
+            asset_dir: std::env::var_os(ASSET_DIR_VAR).map(PathBuf::from),
+            live_reload: false,
+            clock: clock::system_clock(),
+            shutdown_when_empty: false,
+        }
+    }
+}
+
+/// Why a [`GuiServer`] couldn't start
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum GuiError {
+    /// Something else, possibly another GUI, is already listening on the port
+    PortInUse(u16),
+    /// The port is privileged or otherwise forbidden
+    PermissionDenied(u16),
+    /// Any other failure while opening the port
+    Bind(u16, String),
+    /// The recording to play back is missing or malformed
+    Recording(String),
+    /// The asset directory doesn't exist
+    AssetDir(PathBuf),
+}
+
+impl GuiError {
+    fn from_io(port: u16, err: std::io::Error) -> Self {
+        match err.kind() {
+            ErrorKind::AddrInUse => GuiError::PortInUse(port),
+            ErrorKind::PermissionDenied => GuiError::PermissionDenied(port),
+            _ => GuiError::Bind(port, err.to_string()),
+        }
+    }
+}
+
+impl Display for GuiError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            GuiError::PortInUse(port) => write!(f, "port {} is already in use", port),
+            GuiError::PermissionDenied(port) => {
+                write!(f, "permission denied while opening port {}", port)
+            }
+            GuiError::Bind(port, reason) => write!(f, "could not open port {}: {}", port, reason),
+            GuiError::Recording(reason) => write!(f, "could not read the recording: {}", reason),
+            GuiError::AssetDir(dir) => write!(f, "{} is not a directory", dir.display()),
+        }
+    }
+}
+
+impl std::error::Error for GuiError {}
+
+/// The state shared by the threads of a single [`GuiServer`]
+pub(super) struct GuiState {
+    pub(super) guis: Mutex<HashMap<NodeId, DroneGUI>>,
+    pub(super) running: AtomicBool,
+    shutdown_when_empty: bool,
+    pub(super) drop_retention: DropRetention,
+    pub(super) control_token: String,
+    /// `None` leaves reading open to anyone who can reach the ports
+    pub(super) read_token: Option<String>,
+    #[cfg(feature = "gui")]
+    pub(super) web_socket_port: u16,
+    /// The recording served in playback mode, as a JSON array
+    #[cfg(feature = "gui")]
+    pub(super) recording: Option<String>,
+    #[cfg(feature = "gui")]
+    pub(super) clock: Arc<dyn Clock>,
+    #[cfg(feature = "gui")]
+    pub(super) assets: Assets,
+}
+
+impl GuiState {
+    pub(super) fn is_running(&self) -> bool {
+        self.running.load(Ordering::SeqCst)
+    }
+}
+
+/// What a drone needs to report to a [`GuiServer`], obtained with [`GuiServer::handle`]
+#[derive(Clone)]
+pub struct GuiHandle {
+    sender: Sender<GUIMessage>,
+}
+
+impl GuiHandle {
+    pub(super) fn new(sender: Sender<GUIMessage>) -> Self {
+        GuiHandle { sender }
+    }
+
+    pub(super) fn send(&self, message: GUIMessage) {
+        self.sender.send(message).ok();
+    }
+}
+
+/// The HTTP and WebSocket servers of the GUI, together with the thread that collects
+/// the drones' messages.
+///
+/// Every server owns its own drones, so independent simulations can each have their own.
+/// It keeps running until it is shut down or dropped, or with [`GuiConfig::shutdown_when_empty`]
+/// until the last of its drones is removed.
+pub struct GuiServer {
+    sender: Sender<GUIMessage>,
+    state: Arc<GuiState>,
+    shutdown: Option<Sender<()>>,
+    /// Owned by the server threads, so that the port closes once they stop
+    http_server: Weak<Server>,
+    http_port: u16,
+    handles: Vec<JoinHandle<()>>,
+    attached_drones: usize,
+}
+
+impl GuiServer {
+    /// Opens the ports and starts the server threads, nothing is started if a port can't be opened
+    pub fn start(config: GuiConfig) -> Result<GuiServer, GuiError> {
+        Self::start_with(
+            config,
+            #[cfg(feature = "gui")]
+            None,
+        )
+    }
+
+    /// Serves a session recorded by a [`super::GuiRecorder`] instead of live drones
+    #[cfg(feature = "gui")]
+    pub fn playback(config: GuiConfig, recording: impl AsRef<Path>) -> Result<GuiServer, GuiError> {
+        let recording = super::recording::load(recording.as_ref()).map_err(GuiError::Recording)?;
+        Self::start_with(config, Some(recording))
+    }
+
+    fn start_with(
+        config: GuiConfig,
+        #[cfg(feature = "gui")] recording: Option<String>,
+    ) -> Result<GuiServer, GuiError> {
+        if let Some(dir) = config.asset_dir.as_ref().filter(|dir| !dir.is_dir()) {
+            return Err(GuiError::AssetDir(dir.clone()));
+        }
+
+        let http_listener = bind(config.http_port)?;
+        let http_port = local_port(&http_listener, config.http_port);
+        let http_server = Arc::new(
+            Server::from_listener(http_listener, None)
+                .map_err(|err| GuiError::Bind(http_port, err.to_string()))?,
+        );
+
+        #[cfg(feature = "gui")]
+        let tcp_listener = bind(config.web_socket_port)?;
+        #[cfg(feature = "gui")]
+        let web_socket_port = local_port(&tcp_listener, config.web_socket_port);
+
+        let (sender, receiver) = unbounded();
+        let (shutdown_send, shutdown_recv) = bounded(0);
+
+        let state = Arc::new(GuiState {
+            guis: Mutex::new(HashMap::new()),
+            running: AtomicBool::new(true),
+            shutdown_when_empty: config.shutdown_when_empty,
+            drop_retention: config.drop_retention,
+            control_token: config.control_token.unwrap_or_else(generate_token),
+            read_token: match config.read_token {
+                Some(read_token) => Some(read_token),
+                None if config.require_read_token => Some(generate_token()),
+                None => None,
+            },
+            #[cfg(feature = "gui")]
+            web_socket_port,
+            #[cfg(feature = "gui")]
+            recording,
+            #[cfg(feature = "gui")]
+            clock: config.clock,
+            #[cfg(feature = "gui")]
+            assets: Assets::new(config.asset_dir, config.live_reload),
+        });
+
+        match &state.read_token {
+            Some(read_token) => println!("Visit http://localhost:{}/?token={}", http_port, read_token),
+            None => println!("Visit http://localhost:{}", http_port),
+        }
+        println!("Drone control token: {}", state.control_token);
+
+        let mut handles = Vec::new();
+        let receiver_state = state.clone();
+        let receiver_server = http_server.clone();
+        handles.push(thread::spawn(move || {
+            receiver_daemon(receiver, shutdown_recv, &receiver_state, &receiver_server);
+        }));
+        for _ in 0..HTTP_WORKERS {
+            let http_daemon_server = http_server.clone();
+            let http_daemon_state = state.clone();
+            handles.push(thread::spawn(move || {
+                http_daemon(&http_daemon_server, &http_daemon_state);
+            }));
+        }
+
+        #[cfg(feature = "gui")]
+        {
+            let web_socket_daemon_state = state.clone();
+            handles.push(thread::spawn(move || {
+                web_socket_daemon(tcp_listener, web_socket_daemon_state);
+            }));
+        }
+
+        Ok(GuiServer {
+            sender,
+            state,
+            shutdown: Some(shutdown_send),
+            http_server: Arc::downgrade(&http_server),
+            http_port,
+            handles,
+            attached_drones: 0,
+        })
+    }
+
+    /// A handle to give to the drones that should show up in this GUI
+    pub fn handle(&self) -> GuiHandle {
+        GuiHandle::new(self.sender.clone())
+    }
+
+    /// Port of the pages, the one picked by the system if [`GuiConfig::http_port`] was 0
+    pub fn http_port(&self) -> u16 {
+        self.http_port
+    }
+
+    /// Port of the WebSocket, the one picked by the system if
+    /// [`GuiConfig::web_socket_port`] was 0
+    #[cfg(feature = "gui")]
+    pub fn web_socket_port(&self) -> u16 {
+        self.state.web_socket_port
+    }
+
+    /// False once shut down, or once the last drone left with [`GuiConfig::shutdown_when_empty`]
+    pub fn is_running(&self) -> bool {
+        self.state.is_running()
+    }
+
+    /// Stops every server thread and waits for them to finish
+    pub fn shutdown(mut self) {
+        self.stop();
+    }
+
+    pub(super) fn attach(&mut self) {
+        self.attached_drones += 1;
+    }
+
+    /// Returns true if it was the last attached drone
+    pub(super) fn detach(&mut self) -> bool {
+        self.attached_drones = self.attached_drones.saturating_sub(1);
+        self.attached_drones == 0
+    }
+
+    fn stop(&mut self) {
+        if self.handles.is_empty() {
+            return;
+        }
+
+        self.shutdown.take();
+        if let Some(http_server) = self.http_server.upgrade() {
+            wake_to_stop(&self.state, &http_server);
+        }
+
+        // the threads may have stopped on their own, they are joined all the same
+        for handle in self.handles.drain(..) {
+            handle.join().ok();
+        }
+
+        #[cfg(feature = "debug")]
+        println!("GUI server shut down");
+    }
+}
+
+impl Drop for GuiServer {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+/// Makes the server threads notice that the server stopped, unless it already had
+fn wake_to_stop(state: &GuiState, http_server: &Server) {
+    if !state.running.swap(false, Ordering::SeqCst) {
+        return;
+    }
+    // every unblock wakes a single worker
+    for _ in 0..HTTP_WORKERS {
+        http_server.unblock();
+    }
+    #[cfg(feature = "gui")]
+    TcpStream::connect(("127.0.0.1", state.web_socket_port)).ok();
+}
+
+fn bind(port: u16) -> Result<TcpListener, GuiError> {
+    TcpListener::bind(("0.0.0.0", port)).map_err(|err| GuiError::from_io(port, err))
+}
+
+/// The port actually opened, which differs from the requested one when that is 0
+fn local_port(listener: &TcpListener, requested: u16) -> u16 {
+    listener
+        .local_addr()
+        .map_or(requested, |address| address.port())
+}
+
+fn receiver_daemon(
+    receiver: Receiver<GUIMessage>,
+    shutdown: Receiver<()>,
+    state: &GuiState,
+    http_server: &Server,
+) {
+    loop {
+        select! {
+            recv(receiver) -> message => match message {
+                Ok(message) => {
+                    let removal = matches!(message, GUIMessage::DroneRemoved(_));
+                    handle_message(message, state);
+                    if removal && state.shutdown_when_empty && state.guis.lock().unwrap().is_empty() {
+                        wake_to_stop(state, http_server);
+                        break;
+                    }
+                }
+                Err(_) => break,
+            },
+            recv(shutdown) -> _ => break,
+        }
+    }
+}
+
+fn http_daemon(http_server: &Server, state: &GuiState) {
+    loop {
+        match http_server.recv() {
+            Ok(request) => handle_http_request(request, state),
+            Err(_) if !state.is_running() => break,
+            Err(err) => {
+                eprintln!("GUI server could not receive a request: {}", err);
+                thread::sleep(HTTP_ERROR_BACKOFF);
+            }
+        }
+    }
+
+    #[cfg(feature = "debug")]
+    println!("HTTP server shutting down");
+}
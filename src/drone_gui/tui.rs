@@ -0,0 +1,189 @@
+use super::server::GuiHandle;
+use super::{apply_message, DroneGUI, DropRetention, GUIMessage};
+use crate::clock::{self, Clock};
+use crossbeam_channel::{bounded, select, tick, unbounded, Receiver, Sender};
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEventKind};
+use ratatui::layout::Constraint;
+use ratatui::style::{Modifier, Style};
+use ratatui::widgets::{Block, Borders, Row, Table};
+use ratatui::{DefaultTerminal, Frame};
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, SystemTime};
+use wg_2024::network::NodeId;
+
+const REFRESH_INTERVAL: Duration = Duration::from_millis(250);
+/// Anything else printed to the terminal is painted over this often
+const FULL_REDRAW_INTERVAL: Duration = Duration::from_secs(2);
+/// How many seconds of drops the sparklines show
+const SPARKLINE_SECONDS: usize = 30;
+const SPARKLINE_BARS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+
+/// A live terminal view of the drones it is handed to, for when the browser can't reach the GUI.
+///
+/// Takes over the terminal until it is shut down or `q` is pressed.
+pub struct TuiDashboard {
+    sender: Sender<GUIMessage>,
+    shutdown: Option<Sender<()>>,
+    handle: Option<JoinHandle<std::io::Result<()>>>,
+}
+
+impl TuiDashboard {
+    pub fn start() -> std::io::Result<TuiDashboard> {
+        Self::start_with_clock(clock::system_clock())
+    }
+
+    /// Times the sparklines with the drones' clock instead of the wall clock
+    pub fn start_with_clock(clock: Arc<dyn Clock>) -> std::io::Result<TuiDashboard> {
+        let terminal = ratatui::try_init()?;
+        let (sender, receiver) = unbounded();
+        let (shutdown_send, shutdown_recv) = bounded(0);
+        let handle =
+            thread::spawn(move || dashboard_daemon(terminal, receiver, shutdown_recv, clock));
+
+        Ok(TuiDashboard {
+            sender,
+            shutdown: Some(shutdown_send),
+            handle: Some(handle),
+        })
+    }
+
+    /// A handle to give to the drones that should show up in the dashboard
+    pub fn handle(&self) -> GuiHandle {
+        GuiHandle::new(self.sender.clone())
+    }
+
+    /// Gives the terminal back
+    pub fn shutdown(mut self) -> std::io::Result<()> {
+        self.stop()
+    }
+
+    fn stop(&mut self) -> std::io::Result<()> {
+        self.shutdown.take();
+        match self.handle.take() {
+            Some(handle) => handle.join().unwrap_or(Ok(())),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Drop for TuiDashboard {
+    fn drop(&mut self) {
+        self.stop().ok();
+    }
+}
+
+fn dashboard_daemon(
+    mut terminal: DefaultTerminal,
+    receiver: Receiver<GUIMessage>,
+    shutdown: Receiver<()>,
+    clock: Arc<dyn Clock>,
+) -> std::io::Result<()> {
+    let mut guis = HashMap::new();
+    let retention = DropRetention::Window(Duration::from_secs(SPARKLINE_SECONDS as u64));
+    let refresh = tick(REFRESH_INTERVAL);
+    let full_redraw = tick(FULL_REDRAW_INTERVAL);
+
+    let result = loop {
+        select! {
+            recv(receiver) -> message => match message {
+                Ok(message) => apply_message(&mut guis, message, retention),
+                Err(_) => break Ok(()),
+            },
+            recv(shutdown) -> _ => break Ok(()),
+            recv(full_redraw) -> _ => {
+                if let Err(err) = terminal.clear() {
+                    break Err(err);
+                }
+            }
+            recv(refresh) -> _ => {
+                if let Err(err) = terminal.draw(|frame| draw(frame, &guis, clock.now())) {
+                    break Err(err);
+                }
+                match quit_requested() {
+                    Ok(false) => {}
+                    Ok(true) => break Ok(()),
+                    Err(err) => break Err(err),
+                }
+            }
+        }
+    };
+
+    ratatui::restore();
+    result
+}
+
+fn quit_requested() -> std::io::Result<bool> {
+    while event::poll(Duration::ZERO)? {
+        if let Event::Key(key) = event::read()? {
+            if key.kind == KeyEventKind::Press
+                && matches!(key.code, KeyCode::Char('q') | KeyCode::Esc)
+            {
+                return Ok(true);
+            }
+        }
+    }
+    Ok(false)
+}
+
+fn draw(frame: &mut Frame, guis: &HashMap<NodeId, DroneGUI>, now: SystemTime) {
+    let mut ids = guis.keys().collect::<Vec<&NodeId>>();
+    ids.sort();
+
+    let rows = ids.into_iter().map(|id| {
+        let gui = &guis[id];
+        Row::new(vec![
+            id.to_string(),
+            format!("{:.2}", gui.pdr),
+            gui.forwarded.to_string(),
+            gui.exploded.to_string(),
+            sparkline(gui, now),
+            gui.neighbor_list(),
+        ])
+    });
+    let header = Row::new(vec![
+        "Drone",
+        "PDR",
+        "Forwarded",
+        "Dropped",
+        "Drops per second",
+        "Neighbors",
+    ])
+    .style(Style::default().add_modifier(Modifier::BOLD));
+    let widths = [
+        Constraint::Length(5),
+        Constraint::Length(5),
+        Constraint::Length(10),
+        Constraint::Length(9),
+        Constraint::Length(SPARKLINE_SECONDS as u16),
+        Constraint::Min(10),
+    ];
+
+    let table = Table::new(rows, widths).header(header).block(
+        Block::default()
+            .borders(Borders::ALL)
+            .title(" Bagel Bomber · q to quit "),
+    );
+    frame.render_widget(table, frame.area());
+}
+
+/// Explosions of the last seconds, oldest first, one character per second
+fn sparkline(gui: &DroneGUI, now: SystemTime) -> String {
+    let mut buckets = [0usize; SPARKLINE_SECONDS];
+    for drop in gui.drops.iter().filter(|drop| drop.exploded) {
+        let age = now.duration_since(drop.time).unwrap_or_default().as_secs() as usize;
+        if age < SPARKLINE_SECONDS {
+            buckets[SPARKLINE_SECONDS - 1 - age] += 1;
+        }
+    }
+
+    let max = buckets.iter().copied().max().unwrap_or(0).max(1);
+    buckets
+        .iter()
+        .map(|&count| match count {
+            0 => ' ',
+            count => SPARKLINE_BARS[(count * (SPARKLINE_BARS.len() - 1)) / max],
+        })
+        .collect()
+}
@@ -0,0 +1,384 @@
+use super::auth::{Credentials, Scope};
+use super::server::GuiState;
+use super::{DroneGUI, ExplosionStyle};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::io::ErrorKind;
+use std::net::{TcpListener, TcpStream};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant, SystemTime};
+use tungstenite::handshake::server::{Callback, ErrorResponse, Request, Response};
+use tungstenite::http::{header, StatusCode};
+use tungstenite::{Message, WebSocket};
+use wg_2024::network::NodeId;
+
+/// How often a WebSocket connection checks for requests, new drops and shutdown
+const WEB_SOCKET_POLL_INTERVAL: Duration = Duration::from_millis(200);
+/// How often a client hears from an otherwise quiet connection
+const KEEPALIVE_INTERVAL: Duration = Duration::from_secs(15);
+
+pub(super) fn web_socket_daemon(tcp_listener: TcpListener, state: Arc<GuiState>) {
+    let starting_time = state.clock.now() - Duration::from_secs(5);
+    let mut connections: Vec<JoinHandle<()>> = Vec::new();
+
+    for stream in tcp_listener.incoming() {
+        if !state.is_running() {
+            break;
+        }
+
+        if let Ok(stream) = stream {
+            connections.retain(|connection| !connection.is_finished());
+            let state = state.clone();
+            connections.push(thread::spawn(move || {
+                stream.set_read_timeout(Some(WEB_SOCKET_POLL_INTERVAL)).ok();
+                if let Ok(web_socket) = tungstenite::accept_hdr(stream, HandshakeCheck(&state)) {
+                    handle_web_socket_connection(web_socket, starting_time, &state);
+                }
+            }));
+        }
+    }
+
+    for connection in connections {
+        connection.join().ok();
+    }
+
+    #[cfg(feature = "debug")]
+    println!("WebSocket server shutting down");
+}
+
+/// Rejects handshakes without the read token, browsers send the cookie set by the HTTP server
+struct HandshakeCheck<'a>(&'a GuiState);
+
+impl Callback for HandshakeCheck<'_> {
+    fn on_request(self, request: &Request, response: Response) -> Result<Response, ErrorResponse> {
+        let header = |name: header::HeaderName| {
+            request
+                .headers()
+                .get(name)
+                .and_then(|value| value.to_str().ok())
+        };
+        let credentials = Credentials {
+            authorization: header(header::AUTHORIZATION),
+            cookie: header(header::COOKIE),
+            query: request.uri().query().unwrap_or(""),
+        };
+
+        if credentials.scope(self.0) >= Scope::Read {
+            Ok(response)
+        } else {
+            let mut error = ErrorResponse::new(Some("Missing or invalid token".to_string()));
+            *error.status_mut() = StatusCode::UNAUTHORIZED;
+            Err(error)
+        }
+    }
+}
+
+/// Serves the subscription protocol until the client closes the socket or the server stops.
+///
+/// The socket is read on every poll, so that requests and the closing handshake are handled
+/// between updates.
+fn handle_web_socket_connection(
+    mut web_socket: WebSocket<TcpStream>,
+    starting_time: SystemTime,
+    state: &GuiState,
+) {
+    let mut subscription = Subscription::new();
+    let mut last_keepalive = Instant::now();
+
+    while state.is_running() {
+        match web_socket.read() {
+            Ok(Message::Text(text)) => {
+                if let Err(err) = subscription.handle_request(text.as_str()) {
+                    let error = ServerMessage::Error { message: err };
+                    if !send(&mut web_socket, &error) {
+                        break;
+                    }
+                }
+            }
+            Ok(_) => {}
+            Err(tungstenite::Error::Io(err))
+                if matches!(err.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {}
+            Err(_) => break,
+        }
+
+        let messages = subscription.updates(&state.guis.lock().unwrap(), starting_time);
+        if messages
+            .iter()
+            .any(|message| !send(&mut web_socket, message))
+        {
+            break;
+        }
+        if !messages.is_empty() {
+            last_keepalive = Instant::now();
+        }
+
+        if last_keepalive.elapsed() >= KEEPALIVE_INTERVAL {
+            if !send(&mut web_socket, &ServerMessage::Keepalive) {
+                break;
+            }
+            last_keepalive = Instant::now();
+        }
+    }
+
+    web_socket.close(None).ok();
+    web_socket.flush().ok();
+
+    #[cfg(feature = "debug")]
+    println!("WebSocket connection closed");
+}
+
+/// False once the connection is gone
+fn send(web_socket: &mut WebSocket<TcpStream>, message: &ServerMessage) -> bool {
+    let text = serde_json::to_string(message).unwrap_or_default();
+    web_socket.send(Message::Text(text.into())).is_ok()
+}
+
+/// What a client asks for, e.g. `{"type": "subscribe", "drones": [1, 2], "events": ["drops"]}`
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum ClientRequest {
+    /// `events` replaces the events of the whole connection, all of them if never given
+    Subscribe {
+        drones: DroneSelection,
+        events: Option<HashSet<EventKind>>,
+    },
+    Unsubscribe {
+        drones: DroneSelection,
+    },
+}
+
+/// Either a list of ids or `"all"`, which includes the drones that appear later
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum DroneSelection {
+    Ids(Vec<NodeId>),
+    Named(String),
+}
+
+impl DroneSelection {
+    fn is_all(&self) -> Result<bool, String> {
+        match self {
+            DroneSelection::Ids(_) => Ok(false),
+            DroneSelection::Named(name) if name == "all" => Ok(true),
+            DroneSelection::Named(name) => Err(format!("unknown drone selection \"{}\"", name)),
+        }
+    }
+}
+
+#[derive(Deserialize, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+enum EventKind {
+    Drops,
+    Pdr,
+    Counters,
+    ExplosionStyle,
+}
+
+#[derive(Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum ServerMessage {
+    /// Only the drones that changed, with only the subscribed events
+    Update {
+        drones: Vec<DroneUpdate>,
+    },
+    /// A subscribed drone stopped
+    Removed {
+        id: NodeId,
+    },
+    Keepalive,
+    Error {
+        message: String,
+    },
+}
+
+#[derive(Serialize)]
+struct DroneUpdate {
+    id: NodeId,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pdr: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    forwarded: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    exploded: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    explosion_style: Option<ExplosionStyle>,
+    /// Drops since the last update, `time` is in seconds since the server started
+    #[serde(skip_serializing_if = "Option::is_none")]
+    drops: Option<Vec<DropEvent>>,
+}
+
+#[derive(Serialize)]
+struct DropEvent {
+    exploded: bool,
+    time: f32,
+}
+
+/// The part of a drone that is only sent again when it changes
+#[derive(Clone, Copy, PartialEq)]
+struct DroneSnapshot {
+    pdr: f32,
+    forwarded: u64,
+    exploded: u64,
+    explosion_style: ExplosionStyle,
+}
+
+impl From<&DroneGUI> for DroneSnapshot {
+    fn from(gui: &DroneGUI) -> Self {
+        DroneSnapshot {
+            pdr: gui.pdr,
+            forwarded: gui.forwarded,
+            exploded: gui.exploded,
+            explosion_style: gui.explosion_style,
+        }
+    }
+}
+
+struct Subscription {
+    all: bool,
+    ids: HashSet<NodeId>,
+    /// Drones unsubscribed from while subscribed to all of them
+    excluded: HashSet<NodeId>,
+    events: HashSet<EventKind>,
+    sent: HashMap<NodeId, DroneSnapshot>,
+    /// How many bagels each drone had dropped at the last update. Drops are timed by the drones
+    /// and reach the GUI late, so their time doesn't tell which ones the client has already seen
+    drops_seen: HashMap<NodeId, u64>,
+}
+
+impl Subscription {
+    fn new() -> Self {
+        Subscription {
+            all: false,
+            ids: HashSet::new(),
+            excluded: HashSet::new(),
+            events: [
+                EventKind::Drops,
+                EventKind::Pdr,
+                EventKind::Counters,
+                EventKind::ExplosionStyle,
+            ]
+            .into_iter()
+            .collect(),
+            sent: HashMap::new(),
+            drops_seen: HashMap::new(),
+        }
+    }
+
+    fn handle_request(&mut self, text: &str) -> Result<(), String> {
+        let request = serde_json::from_str::<ClientRequest>(text).map_err(|err| err.to_string())?;
+        match request {
+            ClientRequest::Subscribe { drones, events } => {
+                if drones.is_all()? {
+                    self.all = true;
+                    self.excluded.clear();
+                } else if let DroneSelection::Ids(ids) = drones {
+                    for id in ids {
+                        self.excluded.remove(&id);
+                        self.ids.insert(id);
+                    }
+                }
+                if let Some(events) = events {
+                    self.events = events;
+                    // the new events need a full update
+                    self.sent.clear();
+                }
+            }
+            ClientRequest::Unsubscribe { drones } => {
+                if drones.is_all()? {
+                    self.all = false;
+                    self.ids.clear();
+                    self.excluded.clear();
+                } else if let DroneSelection::Ids(ids) = drones {
+                    for id in ids {
+                        self.ids.remove(&id);
+                        if self.all {
+                            self.excluded.insert(id);
+                        }
+                    }
+                }
+                let mut sent = std::mem::take(&mut self.sent);
+                sent.retain(|id, _| self.is_subscribed(*id));
+                self.sent = sent;
+                let mut drops_seen = std::mem::take(&mut self.drops_seen);
+                drops_seen.retain(|id, _| self.is_subscribed(*id));
+                self.drops_seen = drops_seen;
+            }
+        }
+        Ok(())
+    }
+
+    fn is_subscribed(&self, id: NodeId) -> bool {
+        (self.all && !self.excluded.contains(&id)) || self.ids.contains(&id)
+    }
+
+    fn updates(
+        &mut self,
+        guis: &HashMap<NodeId, DroneGUI>,
+        starting_time: SystemTime,
+    ) -> Vec<ServerMessage> {
+        let mut messages = self
+            .sent
+            .keys()
+            .filter(|id| !guis.contains_key(id))
+            .map(|id| ServerMessage::Removed { id: *id })
+            .collect::<Vec<ServerMessage>>();
+        self.sent.retain(|id, _| guis.contains_key(id));
+        self.drops_seen.retain(|id, _| guis.contains_key(id));
+
+        let mut ids = guis
+            .keys()
+            .filter(|id| self.is_subscribed(**id))
+            .collect::<Vec<&NodeId>>();
+        ids.sort();
+
+        let drones = ids
+            .into_iter()
+            .filter_map(|id| self.update(&guis[id], starting_time))
+            .collect::<Vec<DroneUpdate>>();
+        if !drones.is_empty() {
+            messages.push(ServerMessage::Update { drones });
+        }
+
+        messages
+    }
+
+    fn update(&mut self, gui: &DroneGUI, starting_time: SystemTime) -> Option<DroneUpdate> {
+        let snapshot = DroneSnapshot::from(gui);
+        let changed = self.sent.get(&gui.id) != Some(&snapshot);
+        // the retained drops are the latest of every drop, a drone seen for the first time
+        // only streams the drops that come after
+        let dropped = snapshot.forwarded + snapshot.exploded;
+        let seen = self.drops_seen.insert(gui.id, dropped).unwrap_or(dropped);
+        let new_drops = (dropped.saturating_sub(seen) as usize).min(gui.drops.len());
+        let drops = gui
+            .drops
+            .iter()
+            .skip(gui.drops.len() - new_drops)
+            .map(|drop| DropEvent {
+                exploded: drop.exploded,
+                time: drop
+                    .time
+                    .duration_since(starting_time)
+                    .unwrap_or_default()
+                    .as_secs_f32(),
+            })
+            .collect::<Vec<DropEvent>>();
+
+        let wants = |event: EventKind| self.events.contains(&event);
+        if !changed && (drops.is_empty() || !wants(EventKind::Drops)) {
+            return None;
+        }
+
+        let update = DroneUpdate {
+            id: gui.id,
+            pdr: wants(EventKind::Pdr).then_some(snapshot.pdr),
+            forwarded: wants(EventKind::Counters).then_some(snapshot.forwarded),
+            exploded: wants(EventKind::Counters).then_some(snapshot.exploded),
+            explosion_style: wants(EventKind::ExplosionStyle).then_some(snapshot.explosion_style),
+            drops: wants(EventKind::Drops).then_some(drops),
+        };
+        self.sent.insert(gui.id, snapshot);
+        Some(update)
+    }
+}
@@ -1,8 +1,26 @@
 mod bagel_bomber;
+pub mod clock;
 mod coin_toss;
+#[cfg(feature = "conformance")]
+pub mod conformance;
+#[cfg(any(feature = "gui", feature = "metrics"))]
 mod drone_gui;
+#[cfg(any(test, feature = "conformance"))]
+pub mod mock_controller;
+#[cfg(feature = "sim")]
+pub mod sim;
+#[cfg(feature = "topology")]
+pub mod topology;
 
 #[cfg(test)]
 mod tests;
 
-pub use bagel_bomber::BagelBomber;
+pub use bagel_bomber::{BagelBomber, BagelBomberBuilder};
+pub use clock::{Clock, SystemClock, VirtualClock};
+#[cfg(any(feature = "gui", feature = "metrics"))]
+pub use drone_gui::{
+    default_gui_status, DefaultGuiStatus, DropRetention, ExplosionStyle, GuiConfig, GuiError, GuiHandle,
+    GuiRecorder, GuiServer, MAX_WINDOW_DROPS,
+};
+#[cfg(feature = "tui")]
+pub use drone_gui::TuiDashboard;
@@ -0,0 +1,254 @@
+//! A stand-in for the simulation controller that records what drones report to it.
+//!
+//! ```no_run
+//! # use bagel_bomber::mock_controller::{EventMatcher, MockController};
+//! let controller = MockController::new();
+//! // BagelBomber::new(id, controller.sender(), ...), then send it a fragment of session 7
+//! controller.expect(EventMatcher::packet_dropped().session(7));
+//! controller.expect_none(EventMatcher::shortcut());
+//! ```
+//!
+//! Events are timed on arrival, with the controller's [`Clock`].
+
+use crate::clock::{self, Clock};
+use crossbeam_channel::{unbounded, Sender};
+use std::fmt::{Display, Formatter};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
+use wg_2024::controller::DroneEvent;
+use wg_2024::packet::{Packet, PacketType};
+
+/// How often [`MockController::wait_for`] looks at the new events
+const POLL_INTERVAL: Duration = Duration::from_millis(1);
+
+#[derive(Clone, Debug)]
+pub struct RecordedEvent {
+    pub time: SystemTime,
+    pub event: DroneEvent,
+}
+
+impl RecordedEvent {
+    pub fn packet(&self) -> &Packet {
+        packet_of(&self.event)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EventKind {
+    PacketSent,
+    PacketDropped,
+    ControllerShortcut,
+}
+
+impl EventKind {
+    pub fn of(event: &DroneEvent) -> EventKind {
+        match event {
+            DroneEvent::PacketSent(_) => EventKind::PacketSent,
+            DroneEvent::PacketDropped(_) => EventKind::PacketDropped,
+            DroneEvent::ControllerShortcut(_) => EventKind::ControllerShortcut,
+        }
+    }
+}
+
+/// Which events an expectation is about, every field left out matches anything
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct EventMatcher {
+    kind: Option<EventKind>,
+    session: Option<u64>,
+    fragment: Option<u64>,
+}
+
+impl EventMatcher {
+    pub fn any() -> Self {
+        EventMatcher::default()
+    }
+
+    pub fn packet_sent() -> Self {
+        EventMatcher::kind(EventKind::PacketSent)
+    }
+
+    pub fn packet_dropped() -> Self {
+        EventMatcher::kind(EventKind::PacketDropped)
+    }
+
+    pub fn shortcut() -> Self {
+        EventMatcher::kind(EventKind::ControllerShortcut)
+    }
+
+    fn kind(kind: EventKind) -> Self {
+        EventMatcher {
+            kind: Some(kind),
+            ..EventMatcher::default()
+        }
+    }
+
+    pub fn session(mut self, session: u64) -> Self {
+        self.session = Some(session);
+        self
+    }
+
+    /// Fragments, acks and nacks of the given fragment
+    pub fn fragment(mut self, fragment: u64) -> Self {
+        self.fragment = Some(fragment);
+        self
+    }
+
+    pub fn matches(&self, event: &DroneEvent) -> bool {
+        let packet = packet_of(event);
+        let fragment = match &packet.pack_type {
+            PacketType::MsgFragment(fragment) => Some(fragment.fragment_index),
+            PacketType::Ack(ack) => Some(ack.fragment_index),
+            PacketType::Nack(nack) => Some(nack.fragment_index),
+            PacketType::FloodRequest(_) | PacketType::FloodResponse(_) => None,
+        };
+        self.kind.is_none_or(|kind| kind == EventKind::of(event))
+            && self
+                .session
+                .is_none_or(|session| session == packet.session_id)
+            && self.fragment.is_none_or(|index| Some(index) == fragment)
+    }
+}
+
+impl Display for EventMatcher {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self.kind {
+            Some(kind) => write!(f, "{:?}", kind)?,
+            None => write!(f, "any event")?,
+        }
+        if let Some(session) = self.session {
+            write!(f, " for session {}", session)?;
+        }
+        if let Some(fragment) = self.fragment {
+            write!(f, " of fragment {}", fragment)?;
+        }
+        Ok(())
+    }
+}
+
+/// Hands out the `controller_send` of the drones and records every event they send.
+///
+/// The recording goes on until every drone dropped its sender, even after the controller is
+/// dropped.
+pub struct MockController {
+    sender: Sender<DroneEvent>,
+    events: Arc<Mutex<Vec<RecordedEvent>>>,
+    timeout: Duration,
+}
+
+impl Default for MockController {
+    fn default() -> Self {
+        MockController::new()
+    }
+}
+
+impl MockController {
+    pub fn new() -> Self {
+        MockController::with_clock(clock::system_clock())
+    }
+
+    /// Times the events with the given clock, e.g. the one the drones were given
+    pub fn with_clock(clock: Arc<dyn Clock>) -> Self {
+        let (sender, receiver) = unbounded();
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let recorded = events.clone();
+        thread::spawn(move || {
+            for event in receiver.iter() {
+                recorded.lock().unwrap().push(RecordedEvent {
+                    time: clock.now(),
+                    event,
+                });
+            }
+        });
+        MockController {
+            sender,
+            events,
+            timeout: Duration::from_secs(1),
+        }
+    }
+
+    /// How long [`MockController::expect`] waits for a matching event, one second by default
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// The `controller_send` of a drone
+    pub fn sender(&self) -> Sender<DroneEvent> {
+        self.sender.clone()
+    }
+
+    /// Everything recorded so far, oldest first
+    pub fn events(&self) -> Vec<RecordedEvent> {
+        self.events.lock().unwrap().clone()
+    }
+
+    pub fn matching(&self, matcher: &EventMatcher) -> Vec<RecordedEvent> {
+        self.events
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|recorded| matcher.matches(&recorded.event))
+            .cloned()
+            .collect()
+    }
+
+    pub fn count(&self, matcher: &EventMatcher) -> usize {
+        self.matching(matcher).len()
+    }
+
+    /// Forgets what was recorded so far
+    pub fn clear(&self) {
+        self.events.lock().unwrap().clear();
+    }
+
+    /// The first recorded event that matches, waiting for it up to the timeout
+    pub fn wait_for(&self, matcher: &EventMatcher) -> Result<RecordedEvent, String> {
+        let deadline = Instant::now() + self.timeout;
+        loop {
+            if let Some(recorded) = self.matching(matcher).into_iter().next() {
+                return Ok(recorded);
+            }
+            if Instant::now() >= deadline {
+                return Err(format!(
+                    "no {} within {:?}, the controller got {:?}",
+                    matcher,
+                    self.timeout,
+                    self.events()
+                        .iter()
+                        .map(|recorded| &recorded.event)
+                        .collect::<Vec<&DroneEvent>>()
+                ));
+            }
+            thread::sleep(POLL_INTERVAL);
+        }
+    }
+
+    /// Like [`MockController::wait_for`], panicking if no event matches
+    pub fn expect(&self, matcher: EventMatcher) -> RecordedEvent {
+        self.wait_for(&matcher)
+            .unwrap_or_else(|reason| panic!("{}", reason))
+    }
+
+    /// Panics if a matching event was recorded so far, it doesn't wait for more
+    pub fn expect_none(&self, matcher: EventMatcher) {
+        let matching = self.matching(&matcher);
+        assert!(
+            matching.is_empty(),
+            "expected no {}, the controller got {:?}",
+            matcher,
+            matching
+                .iter()
+                .map(|recorded| &recorded.event)
+                .collect::<Vec<&DroneEvent>>()
+        );
+    }
+}
+
+fn packet_of(event: &DroneEvent) -> &Packet {
+    match event {
+        DroneEvent::PacketSent(packet)
+        | DroneEvent::PacketDropped(packet)
+        | DroneEvent::ControllerShortcut(packet) => packet,
+    }
+}
@@ -0,0 +1,433 @@
+//! Runs [`BagelBomber`]s on a topology without any external test harness.
+//!
+//! ```no_run
+//! # use bagel_bomber::sim::Simulation;
+//! let simulation = Simulation::load("topologies/examples/double-chain/topology.toml").unwrap();
+//! let client = simulation.host(100).unwrap();
+//! // client.send(packet), simulation.host(102).unwrap().recv_timeout(...)
+//! simulation.shutdown();
+//! ```
+//!
+//! Timelines of commands and sends can be written as [`scenario::Scenario`]s instead, steady
+//! load between clients and servers as a [`traffic::TrafficProfile`].
+
+pub mod scenario;
+mod session;
+pub mod traffic;
+
+use crate::clock::{self, Clock};
+#[cfg(any(feature = "gui", feature = "metrics"))]
+use crate::GuiHandle;
+use crate::{BagelBomber, BagelBomberBuilder};
+use crossbeam_channel::{unbounded, Receiver, RecvTimeoutError, Sender};
+use std::collections::{BTreeSet, HashMap, VecDeque};
+use std::fmt::{Display, Formatter};
+use std::path::Path;
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+use wg_2024::config::Config;
+use wg_2024::controller::{DroneCommand, DroneEvent};
+use wg_2024::drone::Drone;
+use wg_2024::network::NodeId;
+use wg_2024::packet::{NodeType, Packet};
+
+/// Why a [`Simulation`] couldn't be built
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SimError {
+    /// The topology file couldn't be read
+    Read(String),
+    /// The topology file isn't valid TOML for a [`Config`]
+    Parse(String),
+    /// The topology can't be wired, e.g. a connection to a node that doesn't exist
+    Topology(String),
+    /// A traffic profile doesn't fit the simulation, e.g. a flow from a server
+    Traffic(String),
+}
+
+impl Display for SimError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SimError::Read(reason) => write!(f, "could not read the topology: {}", reason),
+            SimError::Parse(reason) => write!(f, "could not parse the topology: {}", reason),
+            SimError::Topology(reason) => write!(f, "invalid topology: {}", reason),
+            SimError::Traffic(reason) => write!(f, "invalid traffic profile: {}", reason),
+        }
+    }
+}
+
+impl std::error::Error for SimError {}
+
+/// A client or server of the simulation, driven by the test instead of a real implementation
+pub struct Host {
+    id: NodeId,
+    node_type: NodeType,
+    packet_recv: Receiver<Packet>,
+    packet_send: HashMap<NodeId, Sender<Packet>>,
+}
+
+impl Host {
+    pub fn id(&self) -> NodeId {
+        self.id
+    }
+
+    pub fn node_type(&self) -> NodeType {
+        self.node_type
+    }
+
+    /// Sends the packet to the current hop of its routing header, false if that isn't a neighbor
+    pub fn send(&self, packet: Packet) -> bool {
+        packet
+            .routing_header
+            .current_hop()
+            .and_then(|hop| self.packet_send.get(&hop))
+            .is_some_and(|sender| sender.send(packet).is_ok())
+    }
+
+    pub fn recv_timeout(&self, timeout: Duration) -> Result<Packet, RecvTimeoutError> {
+        self.packet_recv.recv_timeout(timeout)
+    }
+
+    /// Everything delivered to this host
+    pub fn receiver(&self) -> &Receiver<Packet> {
+        &self.packet_recv
+    }
+
+    pub fn neighbors(&self) -> impl Iterator<Item = &NodeId> {
+        self.packet_send.keys()
+    }
+}
+
+struct SimDrone {
+    controller_send: Sender<DroneCommand>,
+    handle: JoinHandle<()>,
+}
+
+/// A running topology of [`BagelBomber`]s and [`Host`]s, wired with crossbeam channels.
+///
+/// Drones run on their own threads and stay out of every GUI unless given one.
+pub struct Simulation {
+    drones: HashMap<NodeId, SimDrone>,
+    hosts: HashMap<NodeId, Host>,
+    /// The links that are still up, crashed drones are left out
+    connections: HashMap<NodeId, BTreeSet<NodeId>>,
+    /// The drones' queues, only looked at to tell whether packets are still in flight
+    queues: Vec<Receiver<Packet>>,
+    clock: Arc<dyn Clock>,
+    scripts: Vec<JoinHandle<()>>,
+    events: Receiver<DroneEvent>,
+}
+
+/// Reads a topology file
+pub fn load_config(path: impl AsRef<Path>) -> Result<Config, SimError> {
+    let content = std::fs::read_to_string(path).map_err(|err| SimError::Read(err.to_string()))?;
+    toml::from_str::<Config>(&content).map_err(|err| SimError::Parse(err.to_string()))
+}
+
+impl Simulation {
+    pub fn load(path: impl AsRef<Path>) -> Result<Simulation, SimError> {
+        Simulation::start(&load_config(path)?)
+    }
+
+    pub fn start(config: &Config) -> Result<Simulation, SimError> {
+        Simulation::spawn(config, clock::system_clock(), |builder| builder)
+    }
+
+    /// Like [`Simulation::start`], with the drones shown in the given GUI
+    #[cfg(any(feature = "gui", feature = "metrics"))]
+    pub fn start_with_gui(config: &Config, gui: GuiHandle) -> Result<Simulation, SimError> {
+        Simulation::spawn(config, clock::system_clock(), move |builder| {
+            builder.gui(gui.clone())
+        })
+    }
+
+    /// Like [`Simulation::start`], with the drones and the scenarios reading the time from
+    /// `clock`, e.g. a [`crate::VirtualClock`] to run scenarios instantly
+    pub fn start_with_clock(config: &Config, clock: Arc<dyn Clock>) -> Result<Simulation, SimError> {
+        Simulation::spawn(config, clock, |builder| builder)
+    }
+
+    /// Every drone is given the clock and configured with `configure`, after being kept out of
+    /// the default GUI
+    fn spawn(
+        config: &Config,
+        clock: Arc<dyn Clock>,
+        configure: impl Fn(BagelBomberBuilder) -> BagelBomberBuilder,
+    ) -> Result<Simulation, SimError> {
+        let connections = connections(config)?;
+
+        let mut packet_send = HashMap::new();
+        let mut packet_recv = HashMap::new();
+        for id in connections.keys() {
+            let (sender, receiver) = unbounded();
+            packet_send.insert(*id, sender);
+            packet_recv.insert(*id, receiver);
+        }
+        let neighbors_of = |id: NodeId| {
+            connections[&id]
+                .iter()
+                .map(|neighbor| (*neighbor, packet_send[neighbor].clone()))
+                .collect::<HashMap<NodeId, Sender<Packet>>>()
+        };
+
+        let (events_send, events) = unbounded();
+        let mut drones = HashMap::new();
+        let mut queues = Vec::new();
+        for drone in config.drone.iter() {
+            let packet_recv = packet_recv.remove(&drone.id).unwrap();
+            queues.push(packet_recv.clone());
+            let (controller_send, controller_recv) = unbounded();
+            let builder = BagelBomber::builder(
+                drone.id,
+                events_send.clone(),
+                controller_recv,
+                packet_recv,
+                neighbors_of(drone.id),
+                drone.pdr,
+            );
+            #[cfg(any(feature = "gui", feature = "metrics"))]
+            let builder = builder.without_gui().clock(clock.clone());
+            let mut bagel_bomber = configure(builder).build();
+
+            let handle = thread::spawn(move || bagel_bomber.run());
+            drones.insert(
+                drone.id,
+                SimDrone {
+                    controller_send,
+                    handle,
+                },
+            );
+        }
+
+        let hosts = config
+            .client
+            .iter()
+            .map(|client| (client.id, NodeType::Client))
+            .chain(
+                config
+                    .server
+                    .iter()
+                    .map(|server| (server.id, NodeType::Server)),
+            )
+            .map(|(id, node_type)| {
+                let host = Host {
+                    id,
+                    node_type,
+                    packet_recv: packet_recv.remove(&id).unwrap(),
+                    packet_send: neighbors_of(id),
+                };
+                (id, host)
+            })
+            .collect();
+
+        Ok(Simulation {
+            drones,
+            hosts,
+            connections,
+            queues,
+            clock,
+            scripts: Vec::new(),
+            events,
+        })
+    }
+
+    pub fn clock(&self) -> &Arc<dyn Clock> {
+        &self.clock
+    }
+
+    /// True when no drone has packets waiting and no event is left to read.
+    ///
+    /// A drone may still be handling the packet it just took, so this is a snapshot.
+    pub fn is_idle(&self) -> bool {
+        self.queues.iter().all(|queue| queue.is_empty()) && self.events.is_empty()
+    }
+
+    /// The host, unless it was handed to a script
+    pub fn host(&self, id: NodeId) -> Option<&Host> {
+        self.hosts.get(&id)
+    }
+
+    /// Runs a client or server on its own thread.
+    ///
+    /// The script should return once the host's receiver disconnects, which happens on shutdown.
+    pub fn script(&mut self, id: NodeId, script: impl FnOnce(Host) + Send + 'static) -> bool {
+        match self.hosts.remove(&id) {
+            Some(host) => {
+                self.scripts.push(thread::spawn(move || script(host)));
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// The clients and servers that weren't handed to a script
+    pub fn host_ids(&self) -> impl Iterator<Item = &NodeId> {
+        self.hosts.keys()
+    }
+
+    pub fn drone_ids(&self) -> impl Iterator<Item = &NodeId> {
+        self.drones.keys()
+    }
+
+    /// Sends a command as the simulation controller would, false if the drone doesn't exist
+    /// or already stopped
+    pub fn command(&self, drone: NodeId, command: DroneCommand) -> bool {
+        self.drones
+            .get(&drone)
+            .is_some_and(|drone| drone.controller_send.send(command).is_ok())
+    }
+
+    /// Crashes a drone the way the simulation controller does: its neighbors forget it first.
+    ///
+    /// Hosts handed to a script keep their link, the drone only stops once they are dropped.
+    pub fn crash(&mut self, drone: NodeId) -> bool {
+        if !self.drones.contains_key(&drone) {
+            return false;
+        }
+        let neighbors = match self.connections.remove(&drone) {
+            Some(neighbors) => neighbors,
+            None => return false,
+        };
+        for neighbor in neighbors {
+            self.unlink(neighbor, drone);
+        }
+        self.command(drone, DroneCommand::Crash)
+    }
+
+    /// Removes the link between two nodes from both ends, false if they weren't linked
+    pub fn remove_link(&mut self, a: NodeId, b: NodeId) -> bool {
+        let linked = self
+            .connections
+            .get(&a)
+            .is_some_and(|neighbors| neighbors.contains(&b));
+        if linked {
+            self.unlink(a, b);
+            self.unlink(b, a);
+        }
+        linked
+    }
+
+    /// Makes `node` forget `neighbor`
+    fn unlink(&mut self, node: NodeId, neighbor: NodeId) {
+        if let Some(neighbors) = self.connections.get_mut(&node) {
+            neighbors.remove(&neighbor);
+        }
+        if let Some(host) = self.hosts.get_mut(&node) {
+            host.packet_send.remove(&neighbor);
+        } else {
+            self.command(node, DroneCommand::RemoveSender(neighbor));
+        }
+    }
+
+    /// The shortest path from `from` to `to` over the links that are still up, passing only
+    /// through drones, including both ends
+    pub fn route(&self, from: NodeId, to: NodeId) -> Option<Vec<NodeId>> {
+        let mut previous = HashMap::from([(from, from)]);
+        let mut queue = VecDeque::from([from]);
+        while let Some(node) = queue.pop_front() {
+            if node == to {
+                let mut route = vec![to];
+                while *route.last().unwrap() != from {
+                    route.push(previous[route.last().unwrap()]);
+                }
+                route.reverse();
+                return Some(route);
+            }
+            if node != from && !self.drones.contains_key(&node) {
+                continue;
+            }
+            for neighbor in self.connections.get(&node).into_iter().flatten() {
+                if !previous.contains_key(neighbor) {
+                    previous.insert(*neighbor, node);
+                    queue.push_back(*neighbor);
+                }
+            }
+        }
+        None
+    }
+
+    /// What the drones reported to the controller
+    pub fn events(&self) -> &Receiver<DroneEvent> {
+        &self.events
+    }
+
+    /// Stops every drone and script and waits for them
+    pub fn shutdown(mut self) {
+        self.stop();
+    }
+
+    /// Every link is removed before the drones crash, so that none of them waits for packets
+    /// from a neighbor that is still running
+    fn stop(&mut self) {
+        for (id, drone) in self.drones.iter() {
+            for neighbor in self.connections.get(id).into_iter().flatten() {
+                drone
+                    .controller_send
+                    .send(DroneCommand::RemoveSender(*neighbor))
+                    .ok();
+            }
+            drone.controller_send.send(DroneCommand::Crash).ok();
+        }
+        self.hosts.clear();
+
+        for script in self.scripts.drain(..) {
+            script.join().ok();
+        }
+        for (_, drone) in self.drones.drain() {
+            drone.handle.join().ok();
+        }
+    }
+}
+
+impl Drop for Simulation {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+/// The neighbors of every node, links are bidirectional even if only one end lists them
+fn connections(config: &Config) -> Result<HashMap<NodeId, BTreeSet<NodeId>>, SimError> {
+    let mut connections = HashMap::new();
+    let ids = config
+        .drone
+        .iter()
+        .map(|drone| drone.id)
+        .chain(config.client.iter().map(|client| client.id))
+        .chain(config.server.iter().map(|server| server.id));
+    for id in ids {
+        if connections.insert(id, BTreeSet::new()).is_some() {
+            return Err(SimError::Topology(format!("node {} is defined twice", id)));
+        }
+    }
+
+    let links = config
+        .drone
+        .iter()
+        .map(|drone| (drone.id, &drone.connected_node_ids))
+        .chain(
+            config
+                .client
+                .iter()
+                .map(|client| (client.id, &client.connected_drone_ids)),
+        )
+        .chain(
+            config
+                .server
+                .iter()
+                .map(|server| (server.id, &server.connected_drone_ids)),
+        );
+    for (id, neighbors) in links {
+        for neighbor in neighbors {
+            if !connections.contains_key(neighbor) {
+                return Err(SimError::Topology(format!(
+                    "node {} is connected to unknown node {}",
+                    id, neighbor
+                )));
+            }
+            connections.get_mut(&id).unwrap().insert(*neighbor);
+            connections.get_mut(neighbor).unwrap().insert(id);
+        }
+    }
+
+    Ok(connections)
+}
@@ -0,0 +1,402 @@
+//! Timelines of events played against a running [`Simulation`].
+//!
+//! ```text
+//! # comments and empty lines are ignored
+//! t=1s client 100 sends 100 fragments to 102
+//! t=2s crash drone 3
+//! t=3s set pdr of drone 5 to 0.4
+//! t=4s remove link 6-8
+//! expect server 102 receives everything by t=10s
+//! expect server 103 receives 20 fragments by t=1500ms
+//! ```
+//!
+//! Times are counted from the start of [`Scenario::run`] on the simulation's clock. Senders resend
+//! the fragments that come back as dropped or misrouted, over the route that is shortest at that
+//! moment.
+//!
+//! The clock only moves while no packet is in flight, so with a [`crate::VirtualClock`] handling
+//! packets takes no time and a scenario of minutes runs in milliseconds.
+
+use super::session::{Incoming, Session};
+use super::Simulation;
+use std::collections::{BTreeSet, HashMap};
+use std::fmt::{Display, Formatter};
+use std::path::Path;
+use std::time::Duration;
+use wg_2024::controller::{DroneCommand, DroneEvent};
+use wg_2024::network::NodeId;
+use wg_2024::packet::Packet;
+
+/// How often the runner checks the timeline and the hosts
+const POLL_INTERVAL: Duration = Duration::from_millis(5);
+/// How long the runner waits for the drones while packets are in flight, on the wall clock
+const BUSY_INTERVAL: Duration = Duration::from_micros(200);
+
+/// A line of a scenario that can't be parsed, can't be played or whose expectation failed
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ScenarioError {
+    /// 0 when the file couldn't be read
+    pub line: usize,
+    pub message: String,
+}
+
+impl Display for ScenarioError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl std::error::Error for ScenarioError {}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Action {
+    Crash(NodeId),
+    SetPdr(NodeId, f32),
+    RemoveLink(NodeId, NodeId),
+    Send {
+        from: NodeId,
+        to: NodeId,
+        fragments: u64,
+    },
+}
+
+#[derive(Clone, Debug, PartialEq)]
+struct Step {
+    line: usize,
+    at: Duration,
+    action: Action,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Expected {
+    /// Every fragment the scenario sends to the server
+    Everything,
+    Fragments(u64),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+struct Expectation {
+    line: usize,
+    server: NodeId,
+    expected: Expected,
+    by: Duration,
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Scenario {
+    /// Sorted by time, steps at the same time keep the order of the file
+    steps: Vec<Step>,
+    expectations: Vec<Expectation>,
+}
+
+/// The fragments of one send, identified by the line of the send as session id
+struct Send {
+    session: Session,
+    received: BTreeSet<u64>,
+}
+
+impl Scenario {
+    pub fn load(path: impl AsRef<Path>) -> Result<Scenario, ScenarioError> {
+        let content = std::fs::read_to_string(path).map_err(|err| ScenarioError {
+            line: 0,
+            message: err.to_string(),
+        })?;
+        Scenario::parse(&content)
+    }
+
+    pub fn parse(content: &str) -> Result<Scenario, ScenarioError> {
+        let mut scenario = Scenario::default();
+        for (index, text) in content.lines().enumerate() {
+            let line = index + 1;
+            let text = text.split('#').next().unwrap_or("").trim();
+            if text.is_empty() {
+                continue;
+            }
+            let error = |message: String| ScenarioError { line, message };
+            let words = text.split_whitespace().collect::<Vec<&str>>();
+
+            match words.as_slice() {
+                ["expect", "server", server, "receives", amount, rest @ ..] => {
+                    let (expected, rest) = match (*amount, rest) {
+                        ("everything", rest) => (Expected::Everything, rest),
+                        (amount, ["fragments", rest @ ..]) => {
+                            (Expected::Fragments(number(amount).map_err(error)?), rest)
+                        }
+                        _ => return Err(error(format!("unknown expectation \"{}\"", text))),
+                    };
+                    let by = match rest {
+                        ["by", time] => time_of(time).map_err(error)?,
+                        _ => return Err(error("an expectation ends with \"by t=<time>\"".into())),
+                    };
+                    scenario.expectations.push(Expectation {
+                        line,
+                        server: number(server).map_err(error)?,
+                        expected,
+                        by,
+                    });
+                }
+                [time, rest @ ..] if time.starts_with("t=") => {
+                    let action = action(rest).map_err(error)?;
+                    scenario.steps.push(Step {
+                        line,
+                        at: time_of(time).map_err(error)?,
+                        action,
+                    });
+                }
+                _ => return Err(error(format!("unknown line \"{}\"", text))),
+            }
+        }
+        scenario.steps.sort_by_key(|step| step.at);
+        Ok(scenario)
+    }
+
+    /// Plays the timeline and waits for every expectation to pass or time out.
+    ///
+    /// The runner drives the senders and receivers of the scenario, so those hosts must not be
+    /// handed to scripts, and it takes the controller shortcuts out of [`Simulation::events`].
+    pub fn run(&self, simulation: &mut Simulation) -> Result<(), Vec<ScenarioError>> {
+        let clock = simulation.clock().clone();
+        let start = clock.now();
+        let elapsed = || clock.now().duration_since(start).unwrap_or_default();
+        let mut errors = Vec::new();
+        let mut steps = self.steps.iter().peekable();
+        let mut expectations = self.expectations.iter().collect::<Vec<&Expectation>>();
+        let mut sessions = HashMap::new();
+
+        let hosts = self
+            .steps
+            .iter()
+            .flat_map(|step| match step.action {
+                Action::Send { from, to, .. } => vec![from, to],
+                _ => vec![],
+            })
+            .chain(
+                self.expectations
+                    .iter()
+                    .map(|expectation| expectation.server),
+            )
+            .collect::<BTreeSet<NodeId>>();
+        for host in hosts.iter() {
+            if simulation.host(*host).is_none() {
+                errors.push(ScenarioError {
+                    line: self.first_line_with(*host),
+                    message: format!("{} is not a host of the simulation", host),
+                });
+            }
+        }
+        if !errors.is_empty() {
+            return Err(errors);
+        }
+
+        loop {
+            let now = elapsed();
+            while let Some(step) = steps.next_if(|step| step.at <= now) {
+                if let Err(message) = play(step, simulation, &mut sessions) {
+                    errors.push(ScenarioError {
+                        line: step.line,
+                        message,
+                    });
+                }
+            }
+
+            let mut packets = Vec::new();
+            for host in hosts.iter().filter_map(|id| simulation.host(*id)) {
+                packets.extend(host.receiver().try_iter());
+            }
+            packets.extend(
+                simulation
+                    .events()
+                    .try_iter()
+                    .filter_map(|event| match event {
+                        DroneEvent::ControllerShortcut(packet) => Some(packet),
+                        _ => None,
+                    }),
+            );
+            for packet in packets {
+                handle_packet(packet, simulation, &mut sessions);
+            }
+
+            let now = elapsed();
+            expectations.retain(|expectation| {
+                let received = sessions
+                    .values()
+                    .filter(|send| send.session.to == expectation.server)
+                    .map(|send| send.received.len() as u64)
+                    .sum::<u64>();
+                let wanted = match expectation.expected {
+                    Expected::Everything => self.fragments_sent_to(expectation.server),
+                    Expected::Fragments(fragments) => fragments,
+                };
+                if received >= wanted {
+                    return false;
+                }
+                if now > expectation.by {
+                    errors.push(ScenarioError {
+                        line: expectation.line,
+                        message: format!(
+                            "server {} received {} of {} fragments by {:?}",
+                            expectation.server, received, wanted, expectation.by
+                        ),
+                    });
+                    return false;
+                }
+                true
+            });
+
+            if steps.peek().is_none() && expectations.is_empty() {
+                break;
+            }
+
+            std::thread::yield_now();
+            if simulation.is_idle() {
+                let next = steps
+                    .peek()
+                    .map(|step| step.at)
+                    .into_iter()
+                    .chain(expectations.iter().map(|expectation| expectation.by))
+                    .min()
+                    .unwrap_or_default();
+                // at least a little, so that an expectation at its deadline can time out
+                let until_next = next.saturating_sub(now).max(Duration::from_millis(1));
+                clock.sleep(until_next.min(POLL_INTERVAL));
+            } else {
+                std::thread::sleep(BUSY_INTERVAL);
+            }
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            errors.sort_by_key(|error| error.line);
+            Err(errors)
+        }
+    }
+
+    fn fragments_sent_to(&self, server: NodeId) -> u64 {
+        self.steps
+            .iter()
+            .map(|step| match step.action {
+                Action::Send { to, fragments, .. } if to == server => fragments,
+                _ => 0,
+            })
+            .sum()
+    }
+
+    fn first_line_with(&self, host: NodeId) -> usize {
+        let steps = self.steps.iter().filter_map(|step| match step.action {
+            Action::Send { from, to, .. } if from == host || to == host => Some(step.line),
+            _ => None,
+        });
+        let expectations = self
+            .expectations
+            .iter()
+            .filter(|expectation| expectation.server == host)
+            .map(|expectation| expectation.line);
+        steps.chain(expectations).min().unwrap_or(0)
+    }
+}
+
+fn action(words: &[&str]) -> Result<Action, String> {
+    match words {
+        ["crash", "drone", drone] => Ok(Action::Crash(number(drone)?)),
+        ["set", "pdr", "of", "drone", drone, "to", pdr] => {
+            let pdr = number::<f32>(pdr)?;
+            if !(0.0..=1.0).contains(&pdr) {
+                return Err(format!("pdr {} is not between 0 and 1", pdr));
+            }
+            Ok(Action::SetPdr(number(drone)?, pdr))
+        }
+        ["remove", "link", link] => {
+            let (a, b) = link
+                .split_once(['-', '–'])
+                .ok_or_else(|| format!("a link is written as <id>-<id>, not \"{}\"", link))?;
+            Ok(Action::RemoveLink(number(a)?, number(b)?))
+        }
+        ["client", from, "sends", fragments, "fragments", "to", to] => Ok(Action::Send {
+            from: number(from)?,
+            to: number(to)?,
+            fragments: number(fragments)?,
+        }),
+        _ => Err(format!("unknown event \"{}\"", words.join(" "))),
+    }
+}
+
+fn number<T: std::str::FromStr>(text: &str) -> Result<T, String> {
+    text.parse()
+        .map_err(|_| format!("invalid number \"{}\"", text))
+}
+
+/// `t=2s`, `t=1.5s` or `t=500ms`
+fn time_of(text: &str) -> Result<Duration, String> {
+    let time = text
+        .strip_prefix("t=")
+        .ok_or_else(|| format!("a time is written as t=<time>, not \"{}\"", text))?;
+    let seconds = if let Some(millis) = time.strip_suffix("ms") {
+        number::<f64>(millis)? / 1000.0
+    } else if let Some(seconds) = time.strip_suffix('s') {
+        number::<f64>(seconds)?
+    } else {
+        return Err(format!("a time needs a unit, s or ms, not \"{}\"", text));
+    };
+    Duration::try_from_secs_f64(seconds).map_err(|_| format!("invalid time \"{}\"", text))
+}
+
+fn play(
+    step: &Step,
+    simulation: &mut Simulation,
+    sessions: &mut HashMap<u64, Send>,
+) -> Result<(), String> {
+    match step.action {
+        Action::Crash(drone) => simulation
+            .crash(drone)
+            .then_some(())
+            .ok_or_else(|| format!("drone {} is not running", drone)),
+        Action::SetPdr(drone, pdr) => simulation
+            .command(drone, DroneCommand::SetPacketDropRate(pdr))
+            .then_some(())
+            .ok_or_else(|| format!("drone {} is not running", drone)),
+        Action::RemoveLink(a, b) => simulation
+            .remove_link(a, b)
+            .then_some(())
+            .ok_or_else(|| format!("{} and {} are not linked", a, b)),
+        Action::Send {
+            from,
+            to,
+            fragments,
+        } => {
+            let session = Session {
+                id: step.line as u64,
+                from,
+                to,
+                total: fragments,
+            };
+            let send = sessions.entry(session.id).or_insert(Send {
+                session,
+                received: BTreeSet::new(),
+            });
+            for index in 0..fragments {
+                if !send.session.send(simulation, index) {
+                    return Err(format!("no route from {} to {}", from, to));
+                }
+            }
+            Ok(())
+        }
+    }
+}
+
+/// Counts delivered fragments and resends the ones that come back as nacks
+fn handle_packet(packet: Packet, simulation: &Simulation, sessions: &mut HashMap<u64, Send>) {
+    let send = match sessions.get_mut(&packet.session_id) {
+        Some(send) => send,
+        None => return,
+    };
+    match send.session.incoming(&packet) {
+        Incoming::Delivered(index) => {
+            send.received.insert(index);
+        }
+        Incoming::Lost(index) if !send.received.contains(&index) => {
+            send.session.send(simulation, index);
+        }
+        _ => {}
+    }
+}
@@ -0,0 +1,70 @@
+//! Fragments sent from one host to another, shared by the scenario and traffic runners.
+
+use super::Simulation;
+use wg_2024::network::{NodeId, SourceRoutingHeader};
+use wg_2024::packet::{Fragment, NackType, Packet, PacketType};
+
+/// The fragments one host sends to another under one session id
+pub(super) struct Session {
+    pub(super) id: u64,
+    pub(super) from: NodeId,
+    pub(super) to: NodeId,
+    pub(super) total: u64,
+}
+
+/// What a packet reaching one of the hosts of a session means for it, by fragment index
+pub(super) enum Incoming {
+    Delivered(u64),
+    Acked(u64),
+    /// Dropped or misrouted on the way, to be sent again
+    Lost(u64),
+    Ignored,
+}
+
+impl Session {
+    /// Sends the fragment over the route that is shortest at that moment, false if there is none
+    pub(super) fn send(&self, simulation: &Simulation, index: u64) -> bool {
+        let (route, host) = match (
+            simulation.route(self.from, self.to),
+            simulation.host(self.from),
+        ) {
+            (Some(route), Some(host)) => (route, host),
+            _ => return false,
+        };
+        host.send(Packet::new_fragment(
+            SourceRoutingHeader::with_first_hop(route),
+            self.id,
+            Fragment::from_string(
+                index,
+                self.total,
+                format!("fragment {} of {}", index, self.total),
+            ),
+        ))
+    }
+
+    /// Fragments count once they reach the receiver, acks and nacks once they reach the sender
+    pub(super) fn incoming(&self, packet: &Packet) -> Incoming {
+        if packet.session_id != self.id {
+            return Incoming::Ignored;
+        }
+        let destination = packet.routing_header.hops.last().copied();
+        match &packet.pack_type {
+            PacketType::MsgFragment(fragment) if destination == Some(self.to) => {
+                Incoming::Delivered(fragment.fragment_index)
+            }
+            PacketType::Ack(ack) if destination == Some(self.from) => {
+                Incoming::Acked(ack.fragment_index)
+            }
+            PacketType::Nack(nack)
+                if destination == Some(self.from)
+                    && matches!(
+                        nack.nack_type,
+                        NackType::Dropped | NackType::ErrorInRouting(_)
+                    ) =>
+            {
+                Incoming::Lost(nack.fragment_index)
+            }
+            _ => Incoming::Ignored,
+        }
+    }
+}
@@ -0,0 +1,370 @@
+//! Steady traffic between the clients and servers of a [`Simulation`], and what came of it.
+//!
+//! ```toml
+//! [[flow]]
+//! from = 100
+//! to = 102
+//! rate = 20 # fragments per second
+//!
+//! [[flow]]
+//! from = 101
+//! to = 103
+//! rate = 5
+//! ```
+//!
+//! Servers ack every fragment over the reversed route. Clients resend the fragments that come
+//! back as dropped or misrouted, over the route that is shortest at that moment, like the senders
+//! of a [`super::scenario::Scenario`].
+
+use super::session::{Incoming, Session};
+use super::{SimError, Simulation};
+use serde::Deserialize;
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::Path;
+use std::time::Duration;
+use wg_2024::controller::DroneEvent;
+use wg_2024::network::{NodeId, SourceRoutingHeader};
+use wg_2024::packet::{NodeType, Packet};
+
+/// How long the runner keeps waiting for fragments and acks after the last send
+pub const DRAIN_TIME: Duration = Duration::from_secs(2);
+/// How often the runner checks the flows and the hosts
+const POLL_INTERVAL: Duration = Duration::from_millis(5);
+/// How long the runner waits for the drones while packets are in flight, on the wall clock
+const BUSY_INTERVAL: Duration = Duration::from_micros(200);
+
+/// A client sending fragments to a server at a constant rate
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+pub struct Flow {
+    pub from: NodeId,
+    pub to: NodeId,
+    /// Fragments per second
+    pub rate: f64,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Deserialize)]
+pub struct TrafficProfile {
+    #[serde(default, rename = "flow")]
+    pub flows: Vec<Flow>,
+}
+
+/// What a drone reported to the controller during a run
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct DroneStats {
+    /// Packets forwarded, nacks included
+    pub sent: u64,
+    pub dropped: u64,
+    /// Acks and nacks handed to the controller because the drone couldn't forward them
+    pub shortcuts: u64,
+}
+
+/// The end-to-end result of a [`Flow`]
+#[derive(Clone, Debug, PartialEq)]
+pub struct FlowStats {
+    pub flow: Flow,
+    /// Distinct fragments, resends aren't counted
+    pub sent: u64,
+    pub resent: u64,
+    /// Fragments that found no route, neither when first sent nor when resent
+    pub unroutable: u64,
+    pub delivered: u64,
+    /// Fragments whose ack made it back to the client
+    pub acked: u64,
+    /// From the first send of a fragment to its delivery
+    pub mean_latency: Option<Duration>,
+    pub max_latency: Option<Duration>,
+}
+
+impl FlowStats {
+    /// Fragments that never reached the server
+    pub fn lost(&self) -> u64 {
+        self.sent - self.delivered
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct TrafficReport {
+    /// Time on the simulation's clock, including the drain
+    pub elapsed: Duration,
+    /// Every drone that reported something
+    pub drones: BTreeMap<NodeId, DroneStats>,
+    /// In the order of the profile
+    pub flows: Vec<FlowStats>,
+}
+
+impl TrafficReport {
+    pub fn sent(&self) -> u64 {
+        self.flows.iter().map(|flow| flow.sent).sum()
+    }
+
+    pub fn delivered(&self) -> u64 {
+        self.flows.iter().map(|flow| flow.delivered).sum()
+    }
+}
+
+/// A flow while it runs, its index in the profile is the session id of its fragments
+struct FlowState<'a> {
+    flow: &'a Flow,
+    /// Its total is the number of fragments the flow will have sent by the end of the run
+    session: Session,
+    interval: Duration,
+    next_send: Duration,
+    sent_at: Vec<Duration>,
+    resent: u64,
+    unroutable: BTreeSet<u64>,
+    delivered: BTreeMap<u64, Duration>,
+    acked: BTreeSet<u64>,
+}
+
+impl TrafficProfile {
+    pub fn load(path: impl AsRef<Path>) -> Result<TrafficProfile, SimError> {
+        let content =
+            std::fs::read_to_string(path).map_err(|err| SimError::Read(err.to_string()))?;
+        TrafficProfile::parse(&content)
+    }
+
+    pub fn parse(content: &str) -> Result<TrafficProfile, SimError> {
+        toml::from_str(content).map_err(|err| SimError::Parse(err.to_string()))
+    }
+
+    /// Every client of the simulation sends to every server at the same rate
+    pub fn every_pair(simulation: &Simulation, rate: f64) -> TrafficProfile {
+        let hosts_of = |node_type: NodeType| {
+            simulation
+                .host_ids()
+                .filter(|id| simulation.host(**id).unwrap().node_type() == node_type)
+                .copied()
+                .collect::<BTreeSet<NodeId>>()
+        };
+        let servers = hosts_of(NodeType::Server);
+        let flows = hosts_of(NodeType::Client)
+            .into_iter()
+            .flat_map(|from| {
+                servers.iter().map(move |to| Flow {
+                    from,
+                    to: *to,
+                    rate,
+                })
+            })
+            .collect();
+        TrafficProfile { flows }
+    }
+
+    /// Runs every flow for `duration` on the simulation's clock, then waits up to [`DRAIN_TIME`]
+    /// for the fragments and acks still in flight.
+    ///
+    /// The runner drives the clients and servers of the flows, so those hosts must not be handed
+    /// to scripts, and it takes every event out of [`Simulation::events`].
+    pub fn run(
+        &self,
+        simulation: &mut Simulation,
+        duration: Duration,
+    ) -> Result<TrafficReport, SimError> {
+        let intervals = self.check(simulation)?;
+
+        let clock = simulation.clock().clone();
+        let start = clock.now();
+        let elapsed = || clock.now().duration_since(start).unwrap_or_default();
+        let mut flows = self
+            .flows
+            .iter()
+            .zip(intervals)
+            .enumerate()
+            .map(|(index, (flow, interval))| FlowState {
+                flow,
+                session: Session {
+                    id: index as u64,
+                    from: flow.from,
+                    to: flow.to,
+                    total: duration.as_nanos().div_ceil(interval.as_nanos()).max(1) as u64,
+                },
+                interval,
+                next_send: Duration::ZERO,
+                sent_at: Vec::new(),
+                resent: 0,
+                unroutable: BTreeSet::new(),
+                delivered: BTreeMap::new(),
+                acked: BTreeSet::new(),
+            })
+            .collect::<Vec<FlowState>>();
+        let hosts = self
+            .flows
+            .iter()
+            .flat_map(|flow| [flow.from, flow.to])
+            .collect::<BTreeSet<NodeId>>();
+        let mut drones = BTreeMap::<NodeId, DroneStats>::new();
+
+        loop {
+            let now = elapsed();
+            if now < duration {
+                for state in flows.iter_mut() {
+                    while state.next_send <= now
+                        && (state.sent_at.len() as u64) < state.session.total
+                    {
+                        let index = state.sent_at.len() as u64;
+                        state.sent_at.push(now);
+                        if !state.session.send(simulation, index) {
+                            state.unroutable.insert(index);
+                        }
+                        state.next_send += state.interval;
+                    }
+                }
+            }
+
+            let mut packets = Vec::new();
+            for host in hosts.iter().filter_map(|id| simulation.host(*id)) {
+                packets.extend(host.receiver().try_iter());
+            }
+            for event in simulation.events().try_iter() {
+                let header = match &event {
+                    DroneEvent::PacketSent(packet)
+                    | DroneEvent::PacketDropped(packet)
+                    | DroneEvent::ControllerShortcut(packet) => &packet.routing_header,
+                };
+                // a sent packet already points at the next hop
+                let drone = match &event {
+                    DroneEvent::PacketSent(_) => header
+                        .hop_index
+                        .checked_sub(1)
+                        .and_then(|index| header.hops.get(index).copied()),
+                    _ => header.current_hop(),
+                };
+                if let Some(drone) = drone {
+                    let stats = drones.entry(drone).or_default();
+                    match &event {
+                        DroneEvent::PacketSent(_) => stats.sent += 1,
+                        DroneEvent::PacketDropped(_) => stats.dropped += 1,
+                        DroneEvent::ControllerShortcut(_) => stats.shortcuts += 1,
+                    }
+                }
+                // the controller delivers shortcuts straight to their destination
+                if let DroneEvent::ControllerShortcut(packet) = event {
+                    packets.push(packet);
+                }
+            }
+            for packet in packets {
+                let now = elapsed();
+                handle_packet(packet, simulation, &mut flows, now);
+            }
+
+            let now = elapsed();
+            let settled = flows.iter().all(|state| {
+                state.sent_at.len() as u64 == state.session.total
+                    && state.acked.union(&state.unroutable).count() == state.sent_at.len()
+            });
+            if now >= duration + DRAIN_TIME || (now >= duration && settled) {
+                break;
+            }
+
+            std::thread::yield_now();
+            if simulation.is_idle() {
+                let next = if now < duration {
+                    flows
+                        .iter()
+                        .map(|state| state.next_send)
+                        .min()
+                        .unwrap_or(duration)
+                        .min(duration)
+                } else {
+                    duration + DRAIN_TIME
+                };
+                // at least a little, so that the run reaches its end
+                let until_next = next.saturating_sub(now).max(Duration::from_millis(1));
+                clock.sleep(until_next.min(POLL_INTERVAL));
+            } else {
+                std::thread::sleep(BUSY_INTERVAL);
+            }
+        }
+
+        Ok(TrafficReport {
+            elapsed: elapsed(),
+            drones,
+            flows: flows.into_iter().map(FlowState::stats).collect(),
+        })
+    }
+
+    /// Every flow goes from a client to a server of the simulation at a positive rate
+    /// Returns the time between two fragments of each flow
+    fn check(&self, simulation: &Simulation) -> Result<Vec<Duration>, SimError> {
+        let mut intervals = Vec::new();
+        for flow in self.flows.iter() {
+            let error = |reason: String| {
+                Err(SimError::Traffic(format!(
+                    "flow from {} to {}: {}",
+                    flow.from, flow.to, reason
+                )))
+            };
+            for (id, node_type) in [(flow.from, NodeType::Client), (flow.to, NodeType::Server)] {
+                match simulation.host(id) {
+                    Some(host) if host.node_type() == node_type => {}
+                    Some(_) => return error(format!("{} is not a {:?}", id, node_type)),
+                    None => return error(format!("{} is not a host of the simulation", id)),
+                }
+            }
+            // the interval must fit in a duration, and be at least a nanosecond
+            match Duration::try_from_secs_f64(1.0 / flow.rate) {
+                Ok(interval) if flow.rate.is_finite() && !interval.is_zero() => {
+                    intervals.push(interval)
+                }
+                _ => return error(format!("invalid rate {}", flow.rate)),
+            }
+        }
+        Ok(intervals)
+    }
+}
+
+impl FlowState<'_> {
+    fn stats(self) -> FlowStats {
+        let latencies = self
+            .delivered
+            .iter()
+            .map(|(index, at)| at.saturating_sub(self.sent_at[*index as usize]))
+            .collect::<Vec<Duration>>();
+        FlowStats {
+            flow: self.flow.clone(),
+            sent: self.sent_at.len() as u64,
+            resent: self.resent,
+            unroutable: self.unroutable.len() as u64,
+            delivered: self.delivered.len() as u64,
+            acked: self.acked.len() as u64,
+            mean_latency: (!latencies.is_empty())
+                .then(|| latencies.iter().sum::<Duration>() / latencies.len() as u32),
+            max_latency: latencies.iter().max().copied(),
+        }
+    }
+}
+
+/// Delivers fragments and acks, and resends the fragments that come back as nacks
+fn handle_packet(packet: Packet, simulation: &Simulation, flows: &mut [FlowState], now: Duration) {
+    let state = match flows.get_mut(packet.session_id as usize) {
+        Some(state) => state,
+        None => return,
+    };
+    match state.session.incoming(&packet) {
+        Incoming::Delivered(index) => {
+            if (index as usize) < state.sent_at.len() {
+                state.delivered.entry(index).or_insert(now);
+            }
+            // duplicates are acked again, the first ack may have been the one that got lost
+            let mut hops = packet.routing_header.hops;
+            hops.reverse();
+            if let Some(server) = simulation.host(state.flow.to) {
+                server.send(Packet::new_ack(
+                    SourceRoutingHeader::with_first_hop(hops),
+                    packet.session_id,
+                    index,
+                ));
+            }
+        }
+        Incoming::Acked(index) => {
+            state.acked.insert(index);
+        }
+        Incoming::Lost(index) if !state.delivered.contains_key(&index) => {
+            state.resent += 1;
+            if !state.session.send(simulation, index) {
+                state.unroutable.insert(index);
+            }
+        }
+        _ => {}
+    }
+}
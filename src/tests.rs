@@ -1,13 +1,17 @@
 use drone_tester::{create_test_environment, DummyNode, PDRPolicy, Runnable, TestNodeInstructions};
 use crossbeam_channel::{unbounded, Receiver, Sender};
-use std::collections::HashMap;
+use proptest::prelude::*;
+use std::collections::{BTreeSet, HashMap};
 use std::thread;
 use std::time::Duration;
-use wg_2024::controller::DroneCommand;
+use wg_2024::controller::{DroneCommand, DroneEvent};
 use wg_2024::drone::Drone;
 use wg_2024::network::{NodeId, SourceRoutingHeader};
 use wg_2024::packet::NodeType::Client;
-use wg_2024::packet::{FloodRequest, Fragment, Packet, PacketType, FRAGMENT_DSIZE};
+use wg_2024::packet::{
+    Ack, FloodRequest, FloodResponse, Fragment, Nack, NackType, NodeType, Packet, PacketType,
+    FRAGMENT_DSIZE,
+};
 
 use crate::BagelBomber;
 
@@ -232,3 +236,1307 @@ fn continuous_ping() {
         DummyNode::create_client_server,
     )
 }
+
+#[cfg(feature = "sim")]
+#[test]
+fn simulated_delivery() {
+    use crate::sim::Simulation;
+    use wg_2024::packet::Fragment;
+
+    let simulation = Simulation::load("topologies/examples/double-chain/topology.toml").unwrap();
+    for id in simulation.drone_ids() {
+        simulation.command(*id, DroneCommand::SetPacketDropRate(0.0));
+    }
+
+    let packet = Packet::new_fragment(
+        SourceRoutingHeader::with_first_hop(vec![100, 1, 3, 5, 7, 9, 102]),
+        0,
+        Fragment::from_string(0, 1, "Hello, world!".to_string()),
+    );
+    assert!(simulation.host(100).unwrap().send(packet));
+
+    let delivered = simulation
+        .host(102)
+        .unwrap()
+        .recv_timeout(Duration::from_secs(1))
+        .unwrap();
+    assert!(matches!(delivered.pack_type, PacketType::MsgFragment(_)));
+    assert_eq!(delivered.routing_header.hop_index, 6);
+
+    simulation.shutdown();
+}
+
+#[cfg(feature = "topology")]
+#[test]
+fn shipped_topology_is_valid() {
+    let config = crate::topology::load("topologies/examples/double-chain/topology.toml").unwrap();
+    assert_eq!(config.drone.len(), 10);
+
+    // the drones of this one don't list client 5 and server 6 back
+    let errors = crate::topology::load("topologies/others/first-topology.toml").unwrap_err();
+    let nodes = errors
+        .iter()
+        .map(|error| error.node)
+        .collect::<Vec<Option<NodeId>>>();
+    assert_eq!(nodes, [Some(5), Some(6), Some(6)]);
+}
+
+#[cfg(feature = "topology")]
+#[test]
+fn topology_errors_point_to_entries() {
+    let errors = crate::topology::parse(
+        "[[drone]]\nid = 1\npdr = 1.5\nconnected_node_ids = [2, 3]\n\n\
+         [[drone]]\nid = 2\npdr = 0.1\nconnected_node_ids = [1]\n\n\
+         [[client]]\nid = 2\nconnected_drone_ids = [1]\n",
+    )
+    .unwrap_err();
+    let messages = errors
+        .iter()
+        .map(|error| error.to_string())
+        .collect::<Vec<String>>();
+
+    assert!(messages.contains(&"line 2: node 1: pdr 1.5 is not between 0 and 1".to_string()));
+    assert!(messages.contains(&"line 2: node 1: 3 is not defined".to_string()));
+    assert!(messages.contains(&"line 12: node 2: id already used by the drone on line 7".to_string()));
+}
+
+#[cfg(feature = "topology")]
+#[test]
+fn generated_topologies_are_valid() {
+    use crate::topology::generate::{
+        generate, GeneratorOptions, PdrDistribution, Placement, Shape,
+    };
+
+    let shapes = [
+        Shape::DoubleChain { length: 5 },
+        Shape::Star { drones: 6 },
+        Shape::Ring { drones: 8 },
+        Shape::Tree { depth: 2, branching: 3 },
+        Shape::Grid { width: 4, height: 3 },
+        Shape::Butterfly { stages: 3 },
+        Shape::Random { drones: 12, probability: 0.1 },
+    ];
+    for shape in shapes {
+        for placement in [Placement::Ends, Placement::Random] {
+            let generated = generate(&GeneratorOptions {
+                shape,
+                placement,
+                seed: 7,
+                ..GeneratorOptions::default()
+            })
+            .unwrap();
+            let config = crate::topology::parse(&generated.to_toml()).unwrap();
+            assert_eq!(config.drone.len(), generated.config.drone.len(), "{:?}", shape);
+        }
+    }
+
+    let too_many = [
+        Shape::DoubleChain { length: usize::MAX },
+        Shape::Tree { depth: 64, branching: 2 },
+        Shape::Grid { width: usize::MAX, height: 2 },
+        Shape::Butterfly { stages: 64 },
+        Shape::Butterfly { stages: usize::MAX },
+    ];
+    for shape in too_many {
+        let options = GeneratorOptions {
+            shape,
+            ..GeneratorOptions::default()
+        };
+        assert_eq!(
+            generate(&options).err().as_deref(),
+            Some("the shape has too many drones"),
+            "{:?}",
+            shape
+        );
+    }
+    let random = |probability| GeneratorOptions {
+        shape: Shape::Random { drones: 12, probability },
+        ..GeneratorOptions::default()
+    };
+    assert!(generate(&random(f64::NAN)).is_err());
+    assert!(generate(&random(1.5)).is_err());
+    for pdr in [PdrDistribution::Fixed(f32::NAN), PdrDistribution::Uniform(0.0, f32::INFINITY)] {
+        let options = GeneratorOptions {
+            pdr,
+            ..GeneratorOptions::default()
+        };
+        assert!(generate(&options).is_err(), "{:?}", pdr);
+    }
+    let uniform = |low, high| GeneratorOptions {
+        pdr: PdrDistribution::Uniform(low, high),
+        ..GeneratorOptions::default()
+    };
+    assert_eq!(
+        generate(&uniform(0.5, 0.25)).err().as_deref(),
+        Some("PDR range 0.5..0.25 ends below its start")
+    );
+    let generated = generate(&uniform(0.25, 0.25)).unwrap();
+    assert!(generated.config.drone.iter().all(|drone| drone.pdr == 0.25));
+}
+
+#[cfg(feature = "sim")]
+#[test]
+fn scenario_timeline() {
+    use crate::sim::scenario::Scenario;
+    use crate::sim::Simulation;
+    use crate::{Clock, VirtualClock};
+    use std::sync::Arc;
+    use std::time::{Instant, UNIX_EPOCH};
+
+    let scenario = Scenario::parse(
+        "t=0s client 100 sends 10 fragments to 102\n\
+         t=10s remove link 5-7\n\
+         t=15s crash drone 1\n\
+         t=20s client 101 sends 5 fragments to 103 # over the second chain\n\
+         t=25s client 100 sends 1 fragments to 102\n\
+         expect server 102 receives 10 fragments by t=1s\n\
+         expect server 103 receives everything by t=60s\n",
+    )
+    .unwrap();
+
+    let clock = Arc::new(VirtualClock::default());
+    let config = crate::sim::load_config("topologies/examples/double-chain/topology.toml").unwrap();
+    let mut simulation = Simulation::start_with_clock(&config, clock.clone()).unwrap();
+    for id in simulation.drone_ids() {
+        simulation.command(*id, DroneCommand::SetPacketDropRate(0.0));
+    }
+    let started = Instant::now();
+    let errors = scenario.run(&mut simulation).unwrap_err();
+    assert!(started.elapsed() < Duration::from_secs(5));
+    assert!(clock.now().duration_since(UNIX_EPOCH).unwrap() >= Duration::from_secs(25));
+    assert_eq!(errors.len(), 1);
+    assert_eq!(errors[0].line, 5);
+    assert_eq!(errors[0].message, "no route from 100 to 102");
+
+    let error = Scenario::parse("t=1s crash drone 3\nt=2 crash drone 4").unwrap_err();
+    assert_eq!(error.to_string(), "line 2: a time needs a unit, s or ms, not \"t=2\"");
+
+    let error = Scenario::parse("t=1s set pdr of drone 3 to 0.5\nt=2s set pdr of drone 3 to 1.5")
+        .unwrap_err();
+    assert_eq!(error.to_string(), "line 2: pdr 1.5 is not between 0 and 1");
+    let error = Scenario::parse("t=1s set pdr of drone 3 to NaN").unwrap_err();
+    assert_eq!(error.to_string(), "line 1: pdr NaN is not between 0 and 1");
+
+    simulation.shutdown();
+}
+
+#[cfg(feature = "sim")]
+#[test]
+fn traffic_profile() {
+    use crate::sim::traffic::TrafficProfile;
+    use crate::sim::{SimError, Simulation};
+    use crate::VirtualClock;
+    use std::sync::Arc;
+
+    let config = crate::sim::load_config("topologies/examples/double-chain/topology.toml").unwrap();
+    let mut simulation =
+        Simulation::start_with_clock(&config, Arc::new(VirtualClock::default())).unwrap();
+    let profile = TrafficProfile::load("topologies/examples/double-chain/traffic.toml").unwrap();
+    let report = profile
+        .run(&mut simulation, Duration::from_secs(5))
+        .unwrap();
+
+    // 20 and 5 fragments per second, every drop is resent until it gets through
+    let sent = report.flows.iter().map(|flow| flow.sent).collect::<Vec<u64>>();
+    assert_eq!(sent, vec![100, 25]);
+    assert_eq!(report.delivered(), 125);
+    assert!(report.flows.iter().all(|flow| flow.acked == flow.sent));
+    let dropped = report.drones.values().map(|stats| stats.dropped).sum::<u64>();
+    let resent = report.flows.iter().map(|flow| flow.resent).sum::<u64>();
+    assert_eq!(dropped, resent);
+    assert!(report.drones.contains_key(&1) && report.drones.contains_key(&2));
+
+    assert_eq!(TrafficProfile::every_pair(&simulation, 1.0).flows.len(), 4);
+    let backwards = TrafficProfile::parse("[[flow]]\nfrom = 102\nto = 100\nrate = 1").unwrap();
+    assert_eq!(
+        backwards.run(&mut simulation, Duration::from_secs(1)),
+        Err(SimError::Traffic(
+            "flow from 102 to 100: 102 is not a Client".to_string()
+        ))
+    );
+    for rate in ["1e-300", "1e300", "0", "-1", "inf"] {
+        let profile =
+            TrafficProfile::parse(&format!("[[flow]]\nfrom = 100\nto = 102\nrate = {}", rate))
+                .unwrap();
+        assert_eq!(
+            profile.run(&mut simulation, Duration::from_secs(1)),
+            Err(SimError::Traffic(format!(
+                "flow from 100 to 102: invalid rate {}",
+                rate.parse::<f64>().unwrap()
+            )))
+        );
+    }
+
+    simulation.shutdown();
+}
+
+/// What `bagel-sim --gui` does, the drones have to finish while the GUI is still up
+#[cfg(all(feature = "sim", feature = "gui"))]
+#[test]
+fn simulation_shuts_down_with_gui() {
+    use crate::sim::traffic::TrafficProfile;
+    use crate::sim::Simulation;
+
+    let gui = crate::GuiServer::start(test_gui_config()).unwrap();
+    let config = crate::sim::load_config("topologies/examples/double-chain/topology.toml").unwrap();
+    let mut simulation = Simulation::start_with_gui(&config, gui.handle()).unwrap();
+    let report = TrafficProfile::every_pair(&simulation, 50.0)
+        .run(&mut simulation, Duration::from_millis(200))
+        .unwrap();
+    assert!(report.sent() > 0);
+    eventually("the GUI shows the drones", || {
+        http(gui.http_port(), "GET", "/api/drones/1", &[], "").0 == 200
+    });
+
+    let shutdown = thread::spawn(move || simulation.shutdown());
+    eventually("the simulation shuts down", || shutdown.is_finished());
+    shutdown.join().unwrap();
+    eventually("the GUI removes the drones", || {
+        http(gui.http_port(), "GET", "/api/drones/1", &[], "").0 == 404
+    });
+    gui.shutdown();
+}
+
+#[cfg(feature = "conformance")]
+#[test]
+fn conformance() {
+    let report = crate::conformance::Conformance::bagel_bomber().run();
+    assert!(report.passed(), "{}", report);
+    assert_eq!(report.results.len(), crate::conformance::Conformance::checks().count());
+}
+
+#[test]
+fn controller_events() {
+    use crate::mock_controller::{EventMatcher, MockController};
+
+    let controller = MockController::new();
+    let (commands, controller_recv) = unbounded();
+    let (packets, packet_recv) = unbounded();
+    let (neighbor_send, neighbor) = unbounded();
+    let mut drone = BagelBomber::new(
+        1,
+        controller.sender(),
+        controller_recv,
+        packet_recv,
+        HashMap::from([(2, neighbor_send)]),
+        1.0,
+    );
+    let handle = thread::spawn(move || Drone::run(&mut drone));
+
+    packets
+        .send(Packet::new_fragment(
+            SourceRoutingHeader::with_first_hop(vec![2, 1, 2]),
+            7,
+            Fragment::from_string(0, 1, "Hello, world!".to_string()),
+        ))
+        .unwrap();
+    let dropped = controller.expect(EventMatcher::packet_dropped().session(7).fragment(0));
+    assert!(matches!(dropped.packet().pack_type, PacketType::MsgFragment(_)));
+    let nack = controller.expect(EventMatcher::packet_sent().session(7));
+    assert!(matches!(nack.packet().pack_type, PacketType::Nack(_)));
+    assert!(nack.time >= dropped.time);
+    neighbor.recv_timeout(Duration::from_secs(1)).unwrap();
+    controller.expect_none(EventMatcher::shortcut());
+
+    // the next hop of an ack is gone, the controller has to deliver it
+    packets
+        .send(Packet::new_ack(
+            SourceRoutingHeader::with_first_hop(vec![2, 1, 3]),
+            8,
+            0,
+        ))
+        .unwrap();
+    controller.expect(EventMatcher::shortcut().session(8));
+    assert_eq!(controller.count(&EventMatcher::any()), 3);
+
+    commands.send(DroneCommand::Crash).unwrap();
+    drop(packets);
+    handle.join().unwrap();
+}
+
+#[cfg(any(feature = "gui", feature = "metrics"))]
+const CONTROL_TOKEN: &str = "test-control-token";
+
+/// A GUI of its own on free ports, unaffected by the environment
+#[cfg(any(feature = "gui", feature = "metrics"))]
+fn test_gui_config() -> crate::GuiConfig {
+    crate::GuiConfig {
+        http_port: 0,
+        web_socket_port: 0,
+        control_token: Some(CONTROL_TOKEN.to_string()),
+        read_token: None,
+        asset_dir: None,
+        ..crate::GuiConfig::default()
+    }
+}
+
+/// Sends a bare HTTP/1.1 request, returning the status, the headers and the body
+#[cfg(any(feature = "gui", feature = "metrics"))]
+fn http(port: u16, method: &str, path: &str, headers: &[&str], body: &str) -> (u16, String, String) {
+    use std::io::{Read, Write};
+
+    let mut stream = std::net::TcpStream::connect(("127.0.0.1", port)).unwrap();
+    stream.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+    let mut request = format!(
+        "{} {} HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\nContent-Length: {}\r\n",
+        method,
+        path,
+        body.len()
+    );
+    for header in headers {
+        request.push_str(header);
+        request.push_str("\r\n");
+    }
+    request.push_str("\r\n");
+    request.push_str(body);
+    stream.write_all(request.as_bytes()).unwrap();
+
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+    let (head, body) = response.split_once("\r\n\r\n").unwrap_or((&response, ""));
+    let status = head
+        .split_whitespace()
+        .nth(1)
+        .and_then(|status| status.parse().ok())
+        .unwrap_or(0);
+    // large responses come in chunks, each after its hexadecimal size
+    let body = if head.contains("Transfer-Encoding: chunked") {
+        let mut chunks = String::new();
+        let mut rest = body;
+        while let Some((size, after)) = rest.split_once("\r\n") {
+            let size = usize::from_str_radix(size, 16).unwrap();
+            if size == 0 {
+                break;
+            }
+            chunks.push_str(&after[..size]);
+            rest = &after[size + 2..];
+        }
+        chunks
+    } else {
+        body.to_string()
+    };
+    (status, head.to_string(), body)
+}
+
+/// Posts to a control endpoint with the control token, returning the status
+#[cfg(any(feature = "gui", feature = "metrics"))]
+fn control(port: u16, path: &str, body: &str) -> u16 {
+    let authorization = format!("Authorization: Bearer {}", CONTROL_TOKEN);
+    http(port, "POST", path, &[&authorization], body).0
+}
+
+/// Checks the condition until it holds, the GUI catches up with the drones asynchronously
+#[cfg(any(feature = "gui", feature = "metrics"))]
+fn eventually(description: &str, mut condition: impl FnMut() -> bool) {
+    let deadline = std::time::Instant::now() + Duration::from_secs(5);
+    while !condition() {
+        assert!(std::time::Instant::now() < deadline, "timed out waiting until {}", description);
+        thread::sleep(Duration::from_millis(10));
+    }
+}
+
+#[cfg(any(feature = "gui", feature = "metrics"))]
+#[test]
+fn gui_attached_drones_finish_after_crashing() {
+    let gui = crate::GuiServer::start(test_gui_config()).unwrap();
+    let port = gui.http_port();
+    let (commands_1, controller_recv_1) = unbounded();
+    let (commands_2, controller_recv_2) = unbounded();
+    let (packets_1, packet_recv_1) = unbounded();
+    let (packets_2, packet_recv_2) = unbounded();
+    let spawn = |id, controller_recv, packet_recv, neighbor| {
+        let mut drone = BagelBomber::builder(
+            id,
+            unbounded().0,
+            controller_recv,
+            packet_recv,
+            HashMap::from([neighbor]),
+            0.0,
+        )
+        .gui(gui.handle())
+        .build();
+        thread::spawn(move || Drone::run(&mut drone))
+    };
+    let drone_1 = spawn(1, controller_recv_1, packet_recv_1, (2, packets_2.clone()));
+    let drone_2 = spawn(2, controller_recv_2, packet_recv_2, (1, packets_1.clone()));
+
+    // a link removed from the GUI can be restored, so drone 1 keeps the channel to 2
+    eventually("2 is unlinked", || control(port, "/1/remove-neighbor", "2") == 200);
+    assert_eq!(control(port, "/1/remove-neighbor", "3"), 404);
+    eventually("2 is relinked", || control(port, "/1/restore-neighbor", "2") == 200);
+    eventually("2 is a neighbor again", || {
+        control(port, "/1/restore-neighbor", "2") == 409
+    });
+    assert_eq!(control(port, "/1/restore-neighbor", "3"), 404);
+    assert_eq!(control(port, "/1/add-neighbor", "3"), 404);
+    eventually("2 is unlinked again", || {
+        control(port, "/1/remove-neighbor", "2") == 200
+    });
+
+    // the controller removes the link for good, dropping the kept channel
+    commands_1.send(DroneCommand::RemoveSender(2)).unwrap();
+    commands_2.send(DroneCommand::Crash).unwrap();
+    drop(packets_2);
+    eventually("drone 2 finishes", || drone_2.is_finished());
+    drone_2.join().unwrap();
+    eventually("the GUI removes drone 2", || {
+        http(port, "GET", "/api/drones/2", &[], "").0 == 404
+    });
+
+    assert_eq!(control(port, "/1/crash", ""), 200);
+    drop(packets_1);
+    eventually("drone 1 finishes", || drone_1.is_finished());
+    drone_1.join().unwrap();
+    gui.shutdown();
+}
+
+#[cfg(any(feature = "gui", feature = "metrics"))]
+#[test]
+fn gui_tokens() {
+    let gui = crate::GuiServer::start(crate::GuiConfig {
+        read_token: Some("reader".to_string()),
+        ..test_gui_config()
+    })
+    .unwrap();
+    let port = gui.http_port();
+    let get = |path: &str, headers: &[&str]| http(port, "GET", path, headers, "");
+
+    assert_eq!(get("/api/drones", &[]).0, 401);
+    assert_eq!(get("/api/drones?token=wrong", &[]).0, 401);
+    assert_eq!(get("/api/drones", &["Cookie: bagel_token=wrong"]).0, 401);
+    let (status, head, _) = get("/api/drones?token=reader;%20Domain=example.com", &[]);
+    assert_eq!(status, 401);
+    assert!(!head.contains("Set-Cookie"));
+
+    // the read token given in the URL is remembered
+    let (status, head, _) = get("/api/drones?token=reader", &[]);
+    assert_eq!(status, 200);
+    assert!(head.contains("Set-Cookie: bagel_token=reader; Path=/; HttpOnly; SameSite=Strict"));
+    assert_eq!(get("/api/drones", &["Cookie: bagel_token=reader"]).0, 200);
+    assert_eq!(get("/api/drones", &["Authorization: Bearer reader"]).0, 200);
+
+    // the control token reads as well, but never ends up in a cookie
+    let (status, head, _) = get(&format!("/api/drones?token={}", CONTROL_TOKEN), &[]);
+    assert_eq!(status, 200);
+    assert!(!head.contains("Set-Cookie"));
+
+    // only the control token in the Authorization header controls the drones
+    let control_cookie = format!("Cookie: bagel_token={}", CONTROL_TOKEN);
+    for headers in [&["Authorization: Bearer reader"][..], &[&control_cookie], &[]] {
+        assert_eq!(http(port, "POST", "/1/crash", headers, "").0, 401);
+    }
+    assert_eq!(control(port, "/1/crash", ""), 404);
+    gui.shutdown();
+
+    // a read token that isn't a valid cookie value is never remembered
+    let gui = crate::GuiServer::start(crate::GuiConfig {
+        read_token: Some("read;er".to_string()),
+        ..test_gui_config()
+    })
+    .unwrap();
+    let (status, head, _) = http(gui.http_port(), "GET", "/api/drones?token=read;er", &[], "");
+    assert_eq!(status, 200);
+    assert!(!head.contains("Set-Cookie"));
+    gui.shutdown();
+}
+
+/// A fragment from 0 that drone 1 forwards to 2
+#[cfg(any(feature = "gui", feature = "metrics"))]
+fn fragment_through_1(session_id: u64) -> Packet {
+    Packet::new_fragment(
+        SourceRoutingHeader::with_first_hop(vec![0, 1, 2]),
+        session_id,
+        Fragment::from_string(0, 1, "Hello, world!".to_string()),
+    )
+}
+
+#[cfg(any(feature = "gui", feature = "metrics"))]
+#[test]
+fn gui_drops_follow_the_drones_clock() {
+    use crate::{DropRetention, VirtualClock};
+    use serde_json::{json, Value};
+    use std::sync::Arc;
+
+    let clock = Arc::new(VirtualClock::default());
+    let gui = crate::GuiServer::start(crate::GuiConfig {
+        drop_retention: DropRetention::Window(Duration::from_secs(10)),
+        clock: clock.clone(),
+        ..test_gui_config()
+    })
+    .unwrap();
+    let port = gui.http_port();
+    let get = |path: &str| {
+        let (status, _, body) = http(port, "GET", path, &[], "");
+        (status, serde_json::from_str::<Value>(&body).unwrap())
+    };
+    let (commands, controller_recv) = unbounded();
+    let (packets, packet_recv) = unbounded();
+    let (neighbor_send, neighbor) = unbounded();
+    let mut drone = BagelBomber::builder(
+        1,
+        unbounded().0,
+        controller_recv,
+        packet_recv,
+        HashMap::from([(2, neighbor_send)]),
+        0.0,
+    )
+    .gui(gui.handle())
+    .clock(clock.clone())
+    .build();
+    let handle = thread::spawn(move || Drone::run(&mut drone));
+    let forward = |session_id| {
+        packets.send(fragment_through_1(session_id)).unwrap();
+        neighbor.recv_timeout(Duration::from_secs(1)).unwrap();
+    };
+    let forwarded = |count: u64| {
+        eventually("the GUI counts the drops", || {
+            get("/api/drones/1").1["forwarded"] == json!(count)
+        })
+    };
+
+    forward(0);
+    forward(1);
+    clock.advance(Duration::from_millis(1500));
+    forward(2);
+    forwarded(3);
+    assert_eq!(
+        get("/api/drones/1/history").1,
+        json!([
+            {"time": 0, "forwarded": 2, "exploded": 0, "pdr": 0.0},
+            {"time": 1, "forwarded": 1, "exploded": 0, "pdr": 0.0},
+        ])
+    );
+    assert_eq!(
+        get("/api/drones/1/drops?since=1").1,
+        json!([{"exploded": false, "time": 1.5}])
+    );
+    assert_eq!(get("/api/drones/1/drops?since=soon").0, 400);
+    assert_eq!(get("/api/drones/3/drops").0, 404);
+
+    // the window ends at the latest drop, on the drone's clock
+    clock.advance(Duration::from_millis(18500));
+    forward(3);
+    forwarded(4);
+    let state = get("/api/drones/1").1;
+    assert_eq!(state["drops"], json!([{"exploded": false, "time": 20.0}]));
+    assert_eq!(state["neighbors"], json!([2]));
+    assert_eq!(
+        get("/api/drones/1/history").1,
+        json!([{"time": 20, "forwarded": 1, "exploded": 0, "pdr": 0.0}])
+    );
+
+    commands.send(DroneCommand::Crash).unwrap();
+    drop(packets);
+    handle.join().unwrap();
+    gui.shutdown();
+}
+
+#[cfg(any(feature = "gui", feature = "metrics"))]
+#[test]
+fn gui_recordings_keep_the_drop_times() {
+    use crate::{GuiRecorder, VirtualClock};
+    use serde_json::Value;
+    use std::sync::Arc;
+
+    let path = std::env::temp_dir().join(format!("bagel-bomber-{}.jsonl", std::process::id()));
+    let clock = Arc::new(VirtualClock::default());
+    let recorder = GuiRecorder::create_with_clock(&path, clock.clone()).unwrap();
+    let (commands, controller_recv) = unbounded();
+    let (packets, packet_recv) = unbounded();
+    let (neighbor_send, neighbor) = unbounded();
+    let mut drone = BagelBomber::builder(
+        1,
+        unbounded().0,
+        controller_recv,
+        packet_recv,
+        HashMap::from([(2, neighbor_send)]),
+        0.0,
+    )
+    .gui(recorder.handle())
+    .clock(clock.clone())
+    .build();
+    let handle = thread::spawn(move || Drone::run(&mut drone));
+
+    clock.advance(Duration::from_secs(5));
+    packets.send(fragment_through_1(0)).unwrap();
+    neighbor.recv_timeout(Duration::from_secs(1)).unwrap();
+    clock.advance(Duration::from_secs(7));
+    commands.send(DroneCommand::Crash).unwrap();
+    drop(packets);
+    handle.join().unwrap();
+    recorder.finish().unwrap();
+
+    let recording = std::fs::read_to_string(&path).unwrap();
+    std::fs::remove_file(&path).ok();
+    let lines = recording
+        .lines()
+        .map(|line| serde_json::from_str::<Value>(line).unwrap())
+        .collect::<Vec<Value>>();
+    let drops = lines
+        .iter()
+        .filter(|line| line["event"]["type"] == "BagelDropped")
+        .map(|line| line["time"].as_f64().unwrap())
+        .collect::<Vec<f64>>();
+    assert_eq!(drops, vec![5.0]);
+    assert_eq!(lines.last().unwrap()["event"]["type"], "DroneRemoved");
+    assert_eq!(lines.last().unwrap()["time"], 12.0);
+}
+
+/// A running drone shown in a GUI, forwarding to 2
+#[cfg(any(feature = "gui", feature = "metrics"))]
+struct GuiDrone {
+    commands: Sender<DroneCommand>,
+    packets: Sender<Packet>,
+    neighbor: Receiver<Packet>,
+    handle: thread::JoinHandle<()>,
+}
+
+#[cfg(any(feature = "gui", feature = "metrics"))]
+impl GuiDrone {
+    fn spawn(id: NodeId, gui: &crate::GuiServer) -> Self {
+        Self::spawn_with_clock(id, gui, crate::clock::system_clock())
+    }
+
+    fn spawn_with_clock(
+        id: NodeId,
+        gui: &crate::GuiServer,
+        clock: std::sync::Arc<dyn crate::Clock>,
+    ) -> Self {
+        let (commands, controller_recv) = unbounded();
+        let (packets, packet_recv) = unbounded();
+        let (neighbor_send, neighbor) = unbounded();
+        let mut drone = BagelBomber::builder(
+            id,
+            unbounded().0,
+            controller_recv,
+            packet_recv,
+            HashMap::from([(2, neighbor_send)]),
+            0.0,
+        )
+        .gui(gui.handle())
+        .clock(clock)
+        .build();
+        GuiDrone {
+            commands,
+            packets,
+            neighbor,
+            handle: thread::spawn(move || Drone::run(&mut drone)),
+        }
+    }
+
+    /// Sends a fragment through drone 1 and waits until it reaches 2
+    fn forward(&self, session_id: u64) {
+        self.packets.send(fragment_through_1(session_id)).unwrap();
+        self.neighbor.recv_timeout(Duration::from_secs(1)).unwrap();
+    }
+
+    fn crash(self) {
+        self.commands.send(DroneCommand::Crash).unwrap();
+        drop(self.packets);
+        self.handle.join().unwrap();
+    }
+}
+
+#[cfg(any(feature = "gui", feature = "metrics"))]
+#[test]
+fn gui_metrics() {
+    let gui = crate::GuiServer::start(test_gui_config()).unwrap();
+    let port = gui.http_port();
+    let drone = GuiDrone::spawn(1, &gui);
+    drone.forward(0);
+    drone.forward(1);
+    // 3 isn't a neighbor, the nack goes back towards 0
+    drone
+        .packets
+        .send(Packet::new_fragment(
+            SourceRoutingHeader::with_first_hop(vec![0, 1, 3]),
+            2,
+            Fragment::from_string(0, 1, "Hello, world!".to_string()),
+        ))
+        .unwrap();
+
+    let metrics = || http(port, "GET", "/metrics", &[], "");
+    eventually("the nack is counted", || {
+        metrics()
+            .2
+            .contains("bagel_bomber_nacks_total{drone=\"1\",type=\"error_in_routing\"} 1\n")
+    });
+    let (status, head, body) = metrics();
+    assert_eq!(status, 200);
+    assert!(head.contains("Content-Type: text/plain; version=0.0.4"));
+    for line in [
+        "# TYPE bagel_bomber_fragments_forwarded_total counter",
+        "bagel_bomber_fragments_forwarded_total{drone=\"1\"} 2",
+        "bagel_bomber_fragments_dropped_total{drone=\"1\"} 0",
+        "bagel_bomber_nacks_total{drone=\"1\",type=\"dropped\"} 0",
+        "# TYPE bagel_bomber_pdr gauge",
+        "bagel_bomber_pdr{drone=\"1\"} 0",
+        "bagel_bomber_neighbors{drone=\"1\"} 1",
+    ] {
+        assert!(body.lines().any(|metric| metric == line), "missing {}", line);
+    }
+
+    drone.crash();
+    eventually("the metrics forget the drone", || !metrics().2.contains("drone=\"1\""));
+    gui.shutdown();
+}
+
+#[cfg(any(feature = "gui", feature = "metrics"))]
+#[test]
+fn gui_drop_window_is_capped() {
+    use serde_json::Value;
+
+    let gui = crate::GuiServer::start(test_gui_config()).unwrap();
+    let port = gui.http_port();
+    let drone = GuiDrone::spawn(1, &gui);
+    let count = crate::MAX_WINDOW_DROPS as u64 + 10;
+    for session_id in 0..count {
+        drone.forward(session_id);
+    }
+
+    // every drop is within the default window, but only the latest ones are kept
+    let state = || {
+        let body = http(port, "GET", "/api/drones/1", &[], "").2;
+        serde_json::from_str::<Value>(&body).unwrap()
+    };
+    eventually("every drop is counted", || state()["forwarded"] == count);
+    let drops = state()["drops"].as_array().unwrap().len();
+    assert_eq!(drops, crate::MAX_WINDOW_DROPS);
+
+    drone.crash();
+    gui.shutdown();
+}
+
+#[cfg(any(feature = "gui", feature = "metrics"))]
+#[test]
+fn gui_inspector_keeps_the_latest_packets() {
+    use serde_json::Value;
+
+    let gui = crate::GuiServer::start(test_gui_config()).unwrap();
+    let port = gui.http_port();
+    let packets = |query: &str| {
+        let path = format!("/api/drones/1/packets{}", query);
+        serde_json::from_str::<Vec<Value>>(&http(port, "GET", &path, &[], "").2).unwrap()
+    };
+    let drone = GuiDrone::spawn(1, &gui);
+    for session_id in 0..250 {
+        drone.forward(session_id);
+    }
+
+    // only the last INSPECTOR_CAPACITY packets are kept, numbered since the drone started
+    eventually("every packet is inspected", || {
+        packets("").last().is_some_and(|packet| packet["seq"] == 250)
+    });
+    let kept = packets("");
+    assert_eq!(kept.len(), 200);
+    assert_eq!(kept[0]["seq"], 51);
+    assert_eq!(kept[0]["session_id"], 50);
+    assert_eq!(kept[0]["packet_type"], "MsgFragment");
+    assert_eq!(kept[0]["hops"], serde_json::json!([0, 1, 2]));
+    let seqs = packets("?after=245")
+        .iter()
+        .map(|packet| packet["seq"].as_u64().unwrap())
+        .collect::<Vec<u64>>();
+    assert_eq!(seqs, vec![246, 247, 248, 249, 250]);
+    assert_eq!(http(port, "GET", "/api/drones/1/packets?after=last", &[], "").0, 400);
+    #[cfg(feature = "gui")]
+    assert_eq!(http(port, "GET", "/1/inspector", &[], "").0, 200);
+
+    drone.crash();
+    gui.shutdown();
+}
+
+#[cfg(any(feature = "gui", feature = "metrics"))]
+#[test]
+fn gui_server_start_and_stop() {
+    use crate::{GuiError, GuiServer};
+
+    let gui = GuiServer::start(test_gui_config()).unwrap();
+    let port = gui.http_port();
+    assert_ne!(port, 0);
+    assert_eq!(http(port, "GET", "/api/drones", &[], "").0, 200);
+
+    // a second GUI can't take the ports of the first one
+    let taken = GuiServer::start(crate::GuiConfig {
+        http_port: port,
+        ..test_gui_config()
+    });
+    assert_eq!(taken.err(), Some(GuiError::PortInUse(port)));
+    assert_eq!(GuiError::PortInUse(port).to_string(), format!("port {} is already in use", port));
+    #[cfg(feature = "gui")]
+    {
+        let web_socket_port = gui.web_socket_port();
+        let taken = GuiServer::start(crate::GuiConfig {
+            web_socket_port,
+            ..test_gui_config()
+        });
+        assert_eq!(taken.err(), Some(GuiError::PortInUse(web_socket_port)));
+    }
+
+    // once shut down, nothing answers on the port and it can be opened again
+    let closed = || std::net::TcpStream::connect(("127.0.0.1", port)).is_err();
+    gui.shutdown();
+    eventually("the port is closed", closed);
+    let gui = GuiServer::start(crate::GuiConfig {
+        http_port: port,
+        ..test_gui_config()
+    })
+    .unwrap();
+    assert_eq!(http(port, "GET", "/api/drones", &[], "").0, 200);
+    drop(gui);
+    eventually("the port is closed again", closed);
+}
+
+#[cfg(any(feature = "gui", feature = "metrics"))]
+#[test]
+fn gui_server_shuts_down_when_empty() {
+    use crate::GuiServer;
+
+    // only when asked to, the server outlives its drones
+    let gui = GuiServer::start(test_gui_config()).unwrap();
+    GuiDrone::spawn(1, &gui).crash();
+    eventually("the GUI removes drone 1", || {
+        http(gui.http_port(), "GET", "/api/drones/1", &[], "").0 == 404
+    });
+    assert!(gui.is_running());
+    gui.shutdown();
+
+    let gui = GuiServer::start(crate::GuiConfig {
+        shutdown_when_empty: true,
+        ..test_gui_config()
+    })
+    .unwrap();
+    let port = gui.http_port();
+    let drone_1 = GuiDrone::spawn(1, &gui);
+    let drone_2 = GuiDrone::spawn(2, &gui);
+    eventually("both drones are shown", || {
+        http(port, "GET", "/api/drones/2", &[], "").0 == 200
+    });
+    drone_1.crash();
+    eventually("the GUI removes drone 1", || {
+        http(port, "GET", "/api/drones/1", &[], "").0 == 404
+    });
+    assert!(gui.is_running());
+
+    drone_2.crash();
+    eventually("the GUI stops", || !gui.is_running());
+    eventually("the port is closed", || {
+        std::net::TcpStream::connect(("127.0.0.1", port)).is_err()
+    });
+    gui.shutdown();
+}
+
+#[cfg(feature = "gui")]
+#[test]
+fn gui_asset_dir() {
+    use crate::{GuiError, GuiServer};
+
+    let dir = std::env::temp_dir().join(format!("bagel-bomber-assets-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(dir.join("style.css"), "body { color: red; }").unwrap();
+    let start = |live_reload| {
+        GuiServer::start(crate::GuiConfig {
+            asset_dir: Some(dir.clone()),
+            live_reload,
+            ..test_gui_config()
+        })
+        .unwrap()
+    };
+
+    // overrides are read once, missing files fall back to the embedded ones
+    let gui = start(false);
+    let get = |gui: &GuiServer, path: &str| http(gui.http_port(), "GET", path, &[], "");
+    std::fs::write(dir.join("style.css"), "body { color: blue; }").unwrap();
+    assert_eq!(get(&gui, "/style").2, "body { color: red; }");
+    assert_eq!(get(&gui, "/script").2, include_str!("../assets/script.js"));
+    assert_eq!(get(&gui, "/live-reload").2, "");
+    gui.shutdown();
+
+    // reloading live, the directory is read on every request
+    let gui = start(true);
+    assert_eq!(get(&gui, "/style").2, "body { color: blue; }");
+    std::fs::write(dir.join("style.css"), "body { color: green; }").unwrap();
+    assert_eq!(get(&gui, "/style").2, "body { color: green; }");
+    assert_eq!(get(&gui, "/live-reload").2, include_str!("../assets/live-reload.js"));
+    gui.shutdown();
+
+    std::fs::remove_dir_all(&dir).ok();
+    let missing = GuiServer::start(crate::GuiConfig {
+        asset_dir: Some(dir.clone()),
+        ..test_gui_config()
+    });
+    assert_eq!(missing.err(), Some(GuiError::AssetDir(dir)));
+}
+
+#[cfg(feature = "gui")]
+#[test]
+fn gui_web_socket_subscriptions() {
+    use serde_json::{json, Value};
+    use tungstenite::{Message, WebSocket};
+
+    let gui = crate::GuiServer::start(test_gui_config()).unwrap();
+    let port = gui.http_port();
+    let drone_1 = GuiDrone::spawn(1, &gui);
+    let drone_2 = GuiDrone::spawn(2, &gui);
+    eventually("both drones are shown", || {
+        [1, 2].iter().all(|id| {
+            http(port, "GET", &format!("/api/drones/{}", id), &[], "").0 == 200
+        })
+    });
+
+    let stream = std::net::TcpStream::connect(("127.0.0.1", gui.web_socket_port())).unwrap();
+    stream.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+    let url = format!("ws://127.0.0.1:{}/", gui.web_socket_port());
+    let (mut web_socket, _) = tungstenite::client(url, stream).unwrap();
+    let send = |web_socket: &mut WebSocket<std::net::TcpStream>, request: Value| {
+        web_socket
+            .send(Message::Text(request.to_string().into()))
+            .unwrap();
+    };
+    let receive = |web_socket: &mut WebSocket<std::net::TcpStream>| loop {
+        if let Message::Text(text) = web_socket.read().unwrap() {
+            let message = serde_json::from_str::<Value>(text.as_str()).unwrap();
+            if message["type"] != "keepalive" {
+                return message;
+            }
+        }
+    };
+
+    // only the subscribed drones, with only the subscribed events
+    send(
+        &mut web_socket,
+        json!({"type": "subscribe", "drones": [1], "events": ["counters"]}),
+    );
+    assert_eq!(
+        receive(&mut web_socket),
+        json!({"type": "update", "drones": [{"id": 1, "forwarded": 0, "exploded": 0}]})
+    );
+    drone_1.forward(0);
+    assert_eq!(
+        receive(&mut web_socket),
+        json!({"type": "update", "drones": [{"id": 1, "forwarded": 1, "exploded": 0}]})
+    );
+
+    // subscribing to all of them keeps the events, and drone 1 hasn't changed since
+    send(&mut web_socket, json!({"type": "subscribe", "drones": "all"}));
+    assert_eq!(
+        receive(&mut web_socket),
+        json!({"type": "update", "drones": [{"id": 2, "forwarded": 0, "exploded": 0}]})
+    );
+
+    // requests are handled in order, so the error also confirms the unsubscription
+    send(&mut web_socket, json!({"type": "unsubscribe", "drones": [1]}));
+    send(&mut web_socket, json!({"type": "subscribe", "drones": "some"}));
+    assert_eq!(
+        receive(&mut web_socket),
+        json!({"type": "error", "message": "unknown drone selection \"some\""})
+    );
+
+    // only the removal of a subscribed drone is announced
+    drone_1.crash();
+    eventually("the GUI removes drone 1", || {
+        http(port, "GET", "/api/drones/1", &[], "").0 == 404
+    });
+    drone_2.crash();
+    assert_eq!(receive(&mut web_socket), json!({"type": "removed", "id": 2}));
+    gui.shutdown();
+}
+
+#[cfg(feature = "gui")]
+#[test]
+fn gui_web_socket_streams_every_drop() {
+    use crate::VirtualClock;
+    use serde_json::{json, Value};
+    use std::sync::Arc;
+    use tungstenite::Message;
+
+    // the clock never moves, so the drops can't be told apart by their time
+    let clock = Arc::new(VirtualClock::default());
+    let gui = crate::GuiServer::start(crate::GuiConfig {
+        clock: clock.clone(),
+        ..test_gui_config()
+    })
+    .unwrap();
+    let drone = GuiDrone::spawn_with_clock(1, &gui, clock);
+    eventually("the drone is shown", || {
+        http(gui.http_port(), "GET", "/api/drones/1", &[], "").0 == 200
+    });
+
+    let stream = std::net::TcpStream::connect(("127.0.0.1", gui.web_socket_port())).unwrap();
+    stream.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+    let url = format!("ws://127.0.0.1:{}/", gui.web_socket_port());
+    let (mut web_socket, _) = tungstenite::client(url, stream).unwrap();
+    let request = json!({"type": "subscribe", "drones": [1], "events": ["drops"]});
+    web_socket
+        .send(Message::Text(request.to_string().into()))
+        .unwrap();
+    let mut drops = |count: usize| {
+        let mut drops = Vec::new();
+        while drops.len() < count {
+            if let Message::Text(text) = web_socket.read().unwrap() {
+                let message = serde_json::from_str::<Value>(text.as_str()).unwrap();
+                if message["type"] == "update" {
+                    drops.extend(message["drones"][0]["drops"].as_array().unwrap().clone());
+                }
+            }
+        }
+        drops
+    };
+    for session_id in 0..3 {
+        drone.forward(session_id);
+    }
+    assert_eq!(drops(3), vec![json!({"exploded": false, "time": 5.0}); 3]);
+    drone.forward(3);
+    assert_eq!(drops(1), vec![json!({"exploded": false, "time": 5.0})]);
+
+    drone.crash();
+    gui.shutdown();
+}
+
+/// A drone that isn't running, fed one packet at a time
+struct IdleDrone {
+    drone: BagelBomber,
+    neighbors: Vec<(NodeId, Receiver<Packet>)>,
+    events: Receiver<DroneEvent>,
+}
+
+impl IdleDrone {
+    fn new(id: NodeId, neighbors: &BTreeSet<NodeId>, pdr: f32) -> Self {
+        let (events_send, events) = unbounded();
+        let mut packet_send = HashMap::new();
+        let mut receivers = Vec::new();
+        for neighbor in neighbors.iter().filter(|neighbor| **neighbor != id) {
+            let (sender, receiver) = unbounded();
+            packet_send.insert(*neighbor, sender);
+            receivers.push((*neighbor, receiver));
+        }
+        IdleDrone {
+            drone: BagelBomber::new(id, events_send, unbounded().1, unbounded().1, packet_send, pdr),
+            neighbors: receivers,
+            events,
+        }
+    }
+
+    /// What the drone sent to each neighbor and to the controller
+    fn handle(&mut self, packet: Packet) -> (Vec<(NodeId, Packet)>, Vec<Packet>) {
+        self.drone.handle_packet(packet);
+        let sent = self
+            .neighbors
+            .iter()
+            .flat_map(|(id, receiver)| receiver.try_iter().map(|packet| (*id, packet)))
+            .collect();
+        let shortcuts = self
+            .events
+            .try_iter()
+            .filter_map(|event| match event {
+                DroneEvent::ControllerShortcut(packet) => Some(packet),
+                _ => None,
+            })
+            .collect();
+        (sent, shortcuts)
+    }
+}
+
+fn node_id() -> impl Strategy<Value = NodeId> {
+    1..8u8
+}
+
+fn neighbors() -> impl Strategy<Value = BTreeSet<NodeId>> {
+    prop::collection::btree_set(node_id(), 0..6)
+}
+
+/// Routes that often, but not always, have the drone at the current hop
+fn routing_header(id: NodeId) -> impl Strategy<Value = SourceRoutingHeader> {
+    (prop::collection::vec(node_id(), 0..7), 0..8usize, any::<bool>()).prop_map(
+        move |(mut hops, hop_index, on_route)| {
+            if on_route && hop_index < hops.len() {
+                hops[hop_index] = id;
+            }
+            SourceRoutingHeader { hop_index, hops }
+        },
+    )
+}
+
+fn path_trace() -> impl Strategy<Value = Vec<(NodeId, NodeType)>> {
+    let node_type = prop_oneof![
+        Just(NodeType::Client),
+        Just(NodeType::Drone),
+        Just(NodeType::Server)
+    ];
+    prop::collection::vec((node_id(), node_type), 0..5)
+}
+
+fn nack_type() -> impl Strategy<Value = NackType> {
+    prop_oneof![
+        node_id().prop_map(NackType::ErrorInRouting),
+        Just(NackType::DestinationIsDrone),
+        Just(NackType::Dropped),
+        node_id().prop_map(NackType::UnexpectedRecipient),
+    ]
+}
+
+fn fragment() -> impl Strategy<Value = PacketType> {
+    (0..4u64, 1..4u64).prop_map(|(fragment_index, total_n_fragments)| {
+        PacketType::MsgFragment(Fragment::new(
+            fragment_index,
+            total_n_fragments,
+            [0; FRAGMENT_DSIZE],
+        ))
+    })
+}
+
+/// Ack, Nack and FloodResponse
+fn control_packet() -> impl Strategy<Value = PacketType> {
+    prop_oneof![
+        any::<u64>().prop_map(|fragment_index| PacketType::Ack(Ack { fragment_index })),
+        (any::<u64>(), nack_type()).prop_map(|(fragment_index, nack_type)| {
+            PacketType::Nack(Nack {
+                fragment_index,
+                nack_type,
+            })
+        }),
+        (0..3u64, path_trace()).prop_map(|(flood_id, path_trace)| {
+            PacketType::FloodResponse(FloodResponse {
+                flood_id,
+                path_trace,
+            })
+        }),
+    ]
+}
+
+fn flood_request() -> impl Strategy<Value = FloodRequest> {
+    (0..3u64, node_id(), path_trace()).prop_map(|(flood_id, initiator_id, path_trace)| {
+        FloodRequest {
+            flood_id,
+            initiator_id,
+            path_trace,
+        }
+    })
+}
+
+fn packet(id: NodeId) -> impl Strategy<Value = Packet> {
+    let pack_type = prop_oneof![
+        fragment(),
+        control_packet(),
+        flood_request().prop_map(PacketType::FloodRequest),
+    ];
+    (routing_header(id), 0..4u64, pack_type).prop_map(|(routing_header, session_id, pack_type)| {
+        Packet {
+            routing_header,
+            session_id,
+            pack_type,
+        }
+    })
+}
+
+proptest! {
+    #[test]
+    fn only_the_next_hop_gets_routed_packets(
+        (id, packet) in node_id().prop_flat_map(|id| (Just(id), packet(id))),
+        neighbors in neighbors(),
+        pdr in 0.0..=1.0f32,
+    ) {
+        let (sent, _) = IdleDrone::new(id, &neighbors, pdr).handle(packet);
+        for (neighbor, packet) in sent {
+            if !matches!(packet.pack_type, PacketType::FloodRequest(_)) {
+                prop_assert_eq!(packet.routing_header.current_hop(), Some(neighbor));
+            }
+        }
+    }
+
+    #[test]
+    fn control_packets_are_never_dropped(
+        (id, routing_header) in node_id().prop_flat_map(|id| (Just(id), routing_header(id))),
+        pack_type in control_packet(),
+        neighbors in neighbors(),
+        pdr in 0.0..=1.0f32,
+    ) {
+        let packet = Packet { routing_header, session_id: 0, pack_type };
+        let (sent, shortcuts) = IdleDrone::new(id, &neighbors, pdr).handle(packet.clone());
+        let outputs = sent
+            .into_iter()
+            .map(|(_, packet)| packet)
+            .chain(shortcuts)
+            .collect::<Vec<Packet>>();
+        prop_assert_eq!(outputs.len(), 1);
+        prop_assert_eq!(&outputs[0].pack_type, &packet.pack_type);
+        prop_assert_eq!(&outputs[0].routing_header.hops, &packet.routing_header.hops);
+    }
+
+    #[test]
+    fn floods_go_everywhere_once(
+        id in node_id(),
+        request in flood_request(),
+        neighbors in neighbors(),
+        pdr in 0.0..=1.0f32,
+    ) {
+        let from = request.path_trace.last().map_or(request.initiator_id, |(id, _)| *id);
+        let mut trace = request.path_trace.clone();
+        trace.push((id, NodeType::Drone));
+        let packet = Packet::new_flood_request(SourceRoutingHeader::empty_route(), 0, request);
+        let mut drone = IdleDrone::new(id, &neighbors, pdr);
+
+        let (sent, _) = drone.handle(packet.clone());
+        let mut reached = sent.iter().map(|(neighbor, _)| *neighbor).collect::<Vec<NodeId>>();
+        reached.sort();
+        let expected = neighbors
+            .iter()
+            .filter(|neighbor| **neighbor != id && **neighbor != from)
+            .copied()
+            .collect::<Vec<NodeId>>();
+        prop_assert_eq!(reached, expected);
+        for (_, packet) in sent {
+            match packet.pack_type {
+                PacketType::FloodRequest(request) => prop_assert_eq!(&request.path_trace, &trace),
+                _ => prop_assert!(false, "a new flood is only forwarded"),
+            }
+        }
+
+        let (sent, shortcuts) = drone.handle(packet);
+        let outputs = sent
+            .into_iter()
+            .map(|(_, packet)| packet)
+            .chain(shortcuts)
+            .collect::<Vec<Packet>>();
+        prop_assert_eq!(outputs.len(), 1);
+        match &outputs[0].pack_type {
+            PacketType::FloodResponse(response) => prop_assert_eq!(&response.path_trace, &trace),
+            _ => prop_assert!(false, "a known flood gets a response"),
+        }
+    }
+}
+
+#[test]
+fn nacks_retrace_the_route() {
+    // (drone, hops, hop index, neighbors, pdr, nack route, nack type)
+    let cases = [
+        (1, vec![2, 1, 3], 1, vec![2], 0.0, vec![1, 2], NackType::ErrorInRouting(3)),
+        (1, vec![2, 1, 3], 1, vec![2, 3], 1.0, vec![1, 2], NackType::Dropped),
+        (3, vec![5, 2, 3, 6], 2, vec![2], 0.0, vec![3, 2, 5], NackType::ErrorInRouting(6)),
+        (1, vec![2, 1], 1, vec![2], 0.0, vec![1, 2], NackType::DestinationIsDrone),
+        (4, vec![2, 1, 3], 1, vec![2], 0.0, vec![4, 2], NackType::UnexpectedRecipient(4)),
+    ];
+    for (id, hops, hop_index, neighbors, pdr, route, nack_type) in cases {
+        let packet = Packet {
+            routing_header: SourceRoutingHeader { hop_index, hops },
+            session_id: 0,
+            pack_type: PacketType::MsgFragment(Fragment::new(2, 3, [0; FRAGMENT_DSIZE])),
+        };
+        let neighbors = neighbors.into_iter().collect::<BTreeSet<NodeId>>();
+        let (sent, shortcuts) = IdleDrone::new(id, &neighbors, pdr).handle(packet);
+        let nacks = sent
+            .into_iter()
+            .map(|(_, packet)| packet)
+            .chain(shortcuts)
+            .collect::<Vec<Packet>>();
+        assert_eq!(nacks.len(), 1, "drone {} sends one nack", id);
+        assert_eq!(nacks[0].routing_header.hops, route);
+        assert_eq!(
+            nacks[0].pack_type,
+            PacketType::Nack(Nack {
+                fragment_index: 2,
+                nack_type,
+            })
+        );
+    }
+}
@@ -0,0 +1,318 @@
+//! Loads topology files like `topologies/examples/double-chain/topology.toml` and checks that
+//! they describe a network the drones can actually fly in.
+
+pub mod generate;
+
+use serde::Deserialize;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fmt::{Display, Formatter};
+use std::path::Path;
+use toml::Spanned;
+use wg_2024::config::{Client, Config, Drone, Server};
+use wg_2024::network::NodeId;
+use wg_2024::packet::NodeType;
+
+/// A problem in a topology file, pointing to the entry that causes it when there is one
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TopologyError {
+    /// Line of the offending entry's id
+    pub line: Option<usize>,
+    pub node: Option<NodeId>,
+    pub message: String,
+}
+
+impl Display for TopologyError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        if let Some(line) = self.line {
+            write!(f, "line {}: ", line)?;
+        }
+        if let Some(node) = self.node {
+            write!(f, "node {}: ", node)?;
+        }
+        write!(f, "{}", self.message)
+    }
+}
+
+impl std::error::Error for TopologyError {}
+
+/// Like [`Config`], but lenient enough to report every problem instead of the first one
+#[derive(Deserialize)]
+struct TopologyFile {
+    #[serde(default)]
+    drone: Vec<DroneEntry>,
+    #[serde(default)]
+    client: Vec<HostEntry>,
+    #[serde(default)]
+    server: Vec<HostEntry>,
+}
+
+#[derive(Deserialize)]
+struct DroneEntry {
+    id: Spanned<NodeId>,
+    #[serde(default)]
+    connected_node_ids: Vec<NodeId>,
+    pdr: f32,
+}
+
+#[derive(Deserialize)]
+struct HostEntry {
+    id: Spanned<NodeId>,
+    #[serde(default)]
+    connected_drone_ids: Vec<NodeId>,
+}
+
+/// A node as the checks see it
+struct Node<'a> {
+    id: NodeId,
+    node_type: NodeType,
+    line: usize,
+    connections: &'a [NodeId],
+}
+
+pub fn load(path: impl AsRef<Path>) -> Result<Config, Vec<TopologyError>> {
+    let content = std::fs::read_to_string(path).map_err(|err| {
+        vec![TopologyError {
+            line: None,
+            node: None,
+            message: err.to_string(),
+        }]
+    })?;
+    parse(&content)
+}
+
+/// Parses and validates a topology, returning every problem found
+pub fn parse(content: &str) -> Result<Config, Vec<TopologyError>> {
+    let file = toml::from_str::<TopologyFile>(content).map_err(|err| {
+        vec![TopologyError {
+            line: err.span().map(|span| line_of(content, span.start)),
+            node: None,
+            message: err.message().to_string(),
+        }]
+    })?;
+
+    let nodes = file
+        .drone
+        .iter()
+        .map(|drone| Node {
+            id: *drone.id.get_ref(),
+            node_type: NodeType::Drone,
+            line: line_of(content, drone.id.span().start),
+            connections: &drone.connected_node_ids,
+        })
+        .chain(file.client.iter().map(|client| Node {
+            id: *client.id.get_ref(),
+            node_type: NodeType::Client,
+            line: line_of(content, client.id.span().start),
+            connections: &client.connected_drone_ids,
+        }))
+        .chain(file.server.iter().map(|server| Node {
+            id: *server.id.get_ref(),
+            node_type: NodeType::Server,
+            line: line_of(content, server.id.span().start),
+            connections: &server.connected_drone_ids,
+        }))
+        .collect::<Vec<Node>>();
+
+    let mut errors = Vec::new();
+    check_pdrs(&file, content, &mut errors);
+    let by_id = check_unique_ids(&nodes, &mut errors);
+    check_connections(&nodes, &by_id, &mut errors);
+    if errors.is_empty() {
+        check_connectivity(&nodes, &by_id, &mut errors);
+    }
+
+    if !errors.is_empty() {
+        errors.sort_by_key(|error| error.line);
+        return Err(errors);
+    }
+
+    Ok(Config {
+        drone: file
+            .drone
+            .into_iter()
+            .map(|drone| Drone {
+                id: drone.id.into_inner(),
+                connected_node_ids: drone.connected_node_ids,
+                pdr: drone.pdr,
+            })
+            .collect(),
+        client: file
+            .client
+            .into_iter()
+            .map(|client| Client {
+                id: client.id.into_inner(),
+                connected_drone_ids: client.connected_drone_ids,
+            })
+            .collect(),
+        server: file
+            .server
+            .into_iter()
+            .map(|server| Server {
+                id: server.id.into_inner(),
+                connected_drone_ids: server.connected_drone_ids,
+            })
+            .collect(),
+    })
+}
+
+fn line_of(content: &str, offset: usize) -> usize {
+    content[..offset.min(content.len())]
+        .bytes()
+        .filter(|byte| *byte == b'\n')
+        .count()
+        + 1
+}
+
+fn error(node: &Node, message: String) -> TopologyError {
+    TopologyError {
+        line: Some(node.line),
+        node: Some(node.id),
+        message,
+    }
+}
+
+fn kind(node_type: NodeType) -> &'static str {
+    match node_type {
+        NodeType::Client => "client",
+        NodeType::Drone => "drone",
+        NodeType::Server => "server",
+    }
+}
+
+fn check_pdrs(file: &TopologyFile, content: &str, errors: &mut Vec<TopologyError>) {
+    for drone in file.drone.iter() {
+        if !(0.0..=1.0).contains(&drone.pdr) {
+            errors.push(TopologyError {
+                line: Some(line_of(content, drone.id.span().start)),
+                node: Some(*drone.id.get_ref()),
+                message: format!("pdr {} is not between 0 and 1", drone.pdr),
+            });
+        }
+    }
+}
+
+fn check_unique_ids<'a>(
+    nodes: &'a [Node<'a>],
+    errors: &mut Vec<TopologyError>,
+) -> HashMap<NodeId, &'a Node<'a>> {
+    let mut by_id: HashMap<NodeId, &Node> = HashMap::new();
+    for node in nodes {
+        match by_id.get(&node.id) {
+            Some(first) => errors.push(error(
+                node,
+                format!(
+                    "id already used by the {} on line {}",
+                    kind(first.node_type),
+                    first.line
+                ),
+            )),
+            None => {
+                by_id.insert(node.id, node);
+            }
+        }
+    }
+    by_id
+}
+
+fn check_connections(
+    nodes: &[Node],
+    by_id: &HashMap<NodeId, &Node>,
+    errors: &mut Vec<TopologyError>,
+) {
+    for node in nodes {
+        let mut seen = HashSet::new();
+        for neighbor_id in node.connections {
+            if !seen.insert(neighbor_id) {
+                errors.push(error(node, format!("{} is listed twice", neighbor_id)));
+                continue;
+            }
+            if *neighbor_id == node.id {
+                errors.push(error(node, "is connected to itself".to_string()));
+                continue;
+            }
+            let neighbor = match by_id.get(neighbor_id) {
+                Some(neighbor) => neighbor,
+                None => {
+                    errors.push(error(node, format!("{} is not defined", neighbor_id)));
+                    continue;
+                }
+            };
+            if node.node_type != NodeType::Drone && neighbor.node_type != NodeType::Drone {
+                errors.push(error(
+                    node,
+                    format!(
+                        "a {} can only be linked to drones, {} is a {}",
+                        kind(node.node_type),
+                        neighbor_id,
+                        kind(neighbor.node_type)
+                    ),
+                ));
+                continue;
+            }
+            if !neighbor.connections.contains(&node.id) {
+                errors.push(error(
+                    node,
+                    format!(
+                        "lists {} but the {} on line {} doesn't list it back",
+                        neighbor_id,
+                        kind(neighbor.node_type),
+                        neighbor.line
+                    ),
+                ));
+            }
+        }
+
+        let links = node.connections.len();
+        match node.node_type {
+            NodeType::Client if !(1..=2).contains(&links) => errors.push(error(
+                node,
+                format!("a client needs one or two drones, it has {}", links),
+            )),
+            NodeType::Server if links < 2 => errors.push(error(
+                node,
+                format!("a server needs at least two drones, it has {}", links),
+            )),
+            _ => {}
+        }
+    }
+}
+
+/// Clients and servers don't forward packets, so every node has to be reachable through drones
+fn check_connectivity(
+    nodes: &[Node],
+    by_id: &HashMap<NodeId, &Node>,
+    errors: &mut Vec<TopologyError>,
+) {
+    let start = match nodes.iter().find(|node| node.node_type == NodeType::Drone) {
+        Some(start) => start,
+        None if nodes.is_empty() => return,
+        None => {
+            errors.push(TopologyError {
+                line: None,
+                node: None,
+                message: "there are no drones to connect the nodes".to_string(),
+            });
+            return;
+        }
+    };
+
+    let mut reached = HashSet::from([start.id]);
+    let mut queue = VecDeque::from([start]);
+    while let Some(node) = queue.pop_front() {
+        for neighbor_id in node.connections {
+            if reached.insert(*neighbor_id) {
+                let neighbor = by_id[neighbor_id];
+                if neighbor.node_type == NodeType::Drone {
+                    queue.push_back(neighbor);
+                }
+            }
+        }
+    }
+
+    for node in nodes.iter().filter(|node| !reached.contains(&node.id)) {
+        errors.push(error(
+            node,
+            format!("can't be reached from drone {}", start.id),
+        ));
+    }
+}
@@ -0,0 +1,548 @@
+//! Builds topologies of standard shapes, written in the same format as `topologies/`.
+
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Write;
+use wg_2024::config::{Client, Config, Drone, Server};
+use wg_2024::network::NodeId;
+
+/// How the drones are linked to each other
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Shape {
+    /// Two chains of `length` drones, linked rung by rung, as in `double-chain/description.md`
+    DoubleChain { length: usize },
+    /// One hub linked to every other drone
+    Star { drones: usize },
+    /// Each drone linked to the next one, the last to the first
+    Ring { drones: usize },
+    /// A complete tree, `depth` 0 is just the root
+    Tree { depth: usize, branching: usize },
+    /// `width` by `height` drones, each linked to its horizontal and vertical neighbors
+    Grid { width: usize, height: usize },
+    /// `stages` columns of `2^(stages - 1)` drones, linked straight and across like an FFT
+    Butterfly { stages: usize },
+    /// Erdős–Rényi: every pair linked with `probability`, components are then joined
+    Random { drones: usize, probability: f64 },
+}
+
+impl Shape {
+    fn name(&self) -> &'static str {
+        match self {
+            Shape::DoubleChain { .. } => "Double Chain",
+            Shape::Star { .. } => "Star",
+            Shape::Ring { .. } => "Ring",
+            Shape::Tree { .. } => "Tree",
+            Shape::Grid { .. } => "Grid",
+            Shape::Butterfly { .. } => "Butterfly",
+            Shape::Random { .. } => "Random",
+        }
+    }
+
+    /// `None` if the count doesn't even fit in a `usize`
+    fn drone_count(&self) -> Option<usize> {
+        match *self {
+            Shape::DoubleChain { length } => length.checked_mul(2),
+            Shape::Star { drones } | Shape::Ring { drones } | Shape::Random { drones, .. } => {
+                Some(drones)
+            }
+            Shape::Tree { depth, branching } => (0..=depth).try_fold(0usize, |count, level| {
+                branching
+                    .checked_pow(u32::try_from(level).ok()?)?
+                    .checked_add(count)
+            }),
+            Shape::Grid { width, height } => width.checked_mul(height),
+            Shape::Butterfly { stages } => {
+                let rows = 1usize.checked_shl(u32::try_from(stages.saturating_sub(1)).ok()?)?;
+                stages.checked_mul(rows)
+            }
+        }
+    }
+}
+
+/// How the drones get their PDR
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PdrDistribution {
+    Fixed(f32),
+    /// Anywhere between the two bounds
+    Uniform(f32, f32),
+}
+
+/// Where clients and servers are linked
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Placement {
+    /// Clients on the first drones, servers on the last two, as in the shipped topologies
+    Ends,
+    /// Clients on one or two random drones, servers on two random drones
+    Random,
+}
+
+#[derive(Clone, Debug)]
+pub struct GeneratorOptions {
+    pub shape: Shape,
+    pub pdr: PdrDistribution,
+    pub clients: usize,
+    pub servers: usize,
+    pub placement: Placement,
+    /// The same seed always gives the same topology
+    pub seed: u64,
+}
+
+impl Default for GeneratorOptions {
+    fn default() -> Self {
+        GeneratorOptions {
+            shape: Shape::DoubleChain { length: 5 },
+            pdr: PdrDistribution::Uniform(0.0, 0.25),
+            clients: 2,
+            servers: 2,
+            placement: Placement::Ends,
+            seed: 0,
+        }
+    }
+}
+
+/// A topology together with the `description.md` that goes next to it
+pub struct GeneratedTopology {
+    pub config: Config,
+    pub description: String,
+}
+
+impl GeneratedTopology {
+    /// The topology in the format of the files in `topologies/`
+    pub fn to_toml(&self) -> String {
+        let mut toml = String::new();
+        for drone in self.config.drone.iter() {
+            writeln!(toml, "[[drone]]").unwrap();
+            writeln!(toml, "id = {}", drone.id).unwrap();
+            writeln!(toml, "pdr = {}", drone.pdr).unwrap();
+            writeln!(
+                toml,
+                "connected_node_ids = {:?}\n",
+                drone.connected_node_ids
+            )
+            .unwrap();
+        }
+        let clients = self
+            .config
+            .client
+            .iter()
+            .map(|client| ("client", client.id, &client.connected_drone_ids));
+        let servers = self
+            .config
+            .server
+            .iter()
+            .map(|server| ("server", server.id, &server.connected_drone_ids));
+        for (kind, id, drones) in clients.chain(servers) {
+            writeln!(toml, "[[{}]]", kind).unwrap();
+            writeln!(toml, "id = {}", id).unwrap();
+            writeln!(toml, "connected_drone_ids = {:?}\n", drones).unwrap();
+        }
+        toml.truncate(toml.trim_end().len());
+        toml.push('\n');
+        toml
+    }
+}
+
+/// Fails if the network doesn't fit in the id space, the servers can't get two drones, a
+/// probability or PDR isn't between 0 and 1 or a PDR range ends below its start
+pub fn generate(options: &GeneratorOptions) -> Result<GeneratedTopology, String> {
+    if let Shape::Random { probability, .. } = options.shape {
+        if !(0.0..=1.0).contains(&probability) {
+            return Err(format!(
+                "probability {} is not between 0 and 1",
+                probability
+            ));
+        }
+    }
+    let pdrs = match options.pdr {
+        PdrDistribution::Fixed(pdr) => vec![pdr],
+        PdrDistribution::Uniform(low, high) => vec![low, high],
+    };
+    if let Some(pdr) = pdrs.iter().find(|pdr| !(0.0..=1.0).contains(*pdr)) {
+        return Err(format!("PDR {} is not between 0 and 1", pdr));
+    }
+    if let PdrDistribution::Uniform(low, high) = options.pdr {
+        if low > high {
+            return Err(format!("PDR range {}..{} ends below its start", low, high));
+        }
+    }
+
+    let drone_count = options
+        .shape
+        .drone_count()
+        .ok_or("the shape has too many drones")?;
+    if drone_count == 0 {
+        return Err("the shape has no drones".to_string());
+    }
+    if options.servers > 0 && drone_count < 2 {
+        return Err("servers need at least two drones".to_string());
+    }
+    // ids start at 1, hosts at 100 like in the shipped topologies when there is room
+    let first_host = if drone_count < 100 {
+        100
+    } else {
+        drone_count + 1
+    };
+    if first_host + options.clients + options.servers > NodeId::MAX as usize + 1 {
+        return Err(format!(
+            "{} drones, {} clients and {} servers don't fit in the node ids",
+            drone_count, options.clients, options.servers
+        ));
+    }
+
+    let mut rng = StdRng::seed_from_u64(options.seed);
+    let mut links = drone_links(options.shape, drone_count, &mut rng);
+    let drone_ids = (1..=drone_count)
+        .map(|id| id as NodeId)
+        .collect::<Vec<NodeId>>();
+
+    let mut client_links = Vec::new();
+    let mut server_links = Vec::new();
+    for index in 0..options.clients {
+        let drones = match options.placement {
+            Placement::Ends => vec![drone_ids[index % drone_count]],
+            Placement::Random => {
+                let amount = rng.gen_range(1..=2.min(drone_count));
+                drone_ids
+                    .choose_multiple(&mut rng, amount)
+                    .copied()
+                    .collect()
+            }
+        };
+        client_links.push(((first_host + index) as NodeId, drones));
+    }
+    for index in 0..options.servers {
+        let drones = match options.placement {
+            Placement::Ends => drone_ids[drone_count - 2..].to_vec(),
+            Placement::Random => drone_ids.choose_multiple(&mut rng, 2).copied().collect(),
+        };
+        server_links.push(((first_host + options.clients + index) as NodeId, drones));
+    }
+    for (host, drones) in client_links.iter().chain(server_links.iter()) {
+        for drone in drones {
+            links.entry(*drone).or_default().insert(*host);
+        }
+    }
+
+    let config = Config {
+        drone: drone_ids
+            .iter()
+            .map(|id| Drone {
+                id: *id,
+                connected_node_ids: links.get(id).into_iter().flatten().copied().collect(),
+                pdr: pdr(options.pdr, &mut rng),
+            })
+            .collect(),
+        client: client_links
+            .iter()
+            .map(|(id, drones)| Client {
+                id: *id,
+                connected_drone_ids: drones.clone(),
+            })
+            .collect(),
+        server: server_links
+            .iter()
+            .map(|(id, drones)| Server {
+                id: *id,
+                connected_drone_ids: drones.clone(),
+            })
+            .collect(),
+    };
+    let description = description(options, &config);
+    Ok(GeneratedTopology {
+        config,
+        description,
+    })
+}
+
+/// Rounded to two decimals, like the hand-written topologies
+fn pdr(distribution: PdrDistribution, rng: &mut StdRng) -> f32 {
+    let pdr = match distribution {
+        PdrDistribution::Fixed(pdr) => pdr,
+        PdrDistribution::Uniform(low, high) if low < high => rng.gen_range(low..=high),
+        PdrDistribution::Uniform(low, _) => low,
+    };
+    (pdr.clamp(0.0, 1.0) * 100.0).round() / 100.0
+}
+
+type Links = BTreeMap<NodeId, BTreeSet<NodeId>>;
+
+fn link(links: &mut Links, a: usize, b: usize) {
+    if a != b {
+        links.entry(a as NodeId).or_default().insert(b as NodeId);
+        links.entry(b as NodeId).or_default().insert(a as NodeId);
+    }
+}
+
+/// Links between drones, which are numbered from 1
+fn drone_links(shape: Shape, drone_count: usize, rng: &mut StdRng) -> Links {
+    let mut links = Links::new();
+    match shape {
+        // odd ids on the first chain, even ids on the second
+        Shape::DoubleChain { length } => {
+            for position in 0..length {
+                let top = 2 * position + 1;
+                link(&mut links, top, top + 1);
+                if position + 1 < length {
+                    link(&mut links, top, top + 2);
+                    link(&mut links, top + 1, top + 3);
+                }
+            }
+        }
+        Shape::Star { drones } => {
+            for leaf in 2..=drones {
+                link(&mut links, 1, leaf);
+            }
+        }
+        Shape::Ring { drones } => {
+            for id in 1..drones {
+                link(&mut links, id, id + 1);
+            }
+            if drones > 2 {
+                link(&mut links, drones, 1);
+            }
+        }
+        // breadth first numbering, the children of `id` follow the ones of `id - 1`
+        Shape::Tree { depth, branching } => {
+            for id in 1..=drone_count {
+                for child in 0..branching {
+                    let child_id = (id - 1) * branching + child + 2;
+                    if child_id > drone_count || depth == 0 {
+                        break;
+                    }
+                    link(&mut links, id, child_id);
+                }
+            }
+        }
+        Shape::Grid { width, height } => {
+            for row in 0..height {
+                for column in 0..width {
+                    let id = row * width + column + 1;
+                    if column + 1 < width {
+                        link(&mut links, id, id + 1);
+                    }
+                    if row + 1 < height {
+                        link(&mut links, id, id + width);
+                    }
+                }
+            }
+        }
+        // column by column, the drone at `row` of a stage goes straight and across
+        // to the row that differs in the stage's bit
+        Shape::Butterfly { stages } => {
+            let rows = 1 << stages.saturating_sub(1);
+            let id = |stage: usize, row: usize| stage * rows + row + 1;
+            for stage in 0..stages.saturating_sub(1) {
+                for row in 0..rows {
+                    link(&mut links, id(stage, row), id(stage + 1, row));
+                    link(
+                        &mut links,
+                        id(stage, row),
+                        id(stage + 1, row ^ (1 << stage)),
+                    );
+                }
+            }
+        }
+        Shape::Random {
+            drones,
+            probability,
+        } => {
+            for a in 1..=drones {
+                for b in a + 1..=drones {
+                    if rng.gen_bool(probability.clamp(0.0, 1.0)) {
+                        link(&mut links, a, b);
+                    }
+                }
+            }
+            join_components(&mut links, drones, rng);
+        }
+    }
+    links
+}
+
+/// Links a random drone of every component to a random drone of the ones before it
+fn join_components(links: &mut Links, drones: usize, rng: &mut StdRng) {
+    let mut reached = BTreeSet::new();
+    let mut joined = Vec::new();
+    for start in 1..=drones as NodeId {
+        if reached.contains(&start) {
+            continue;
+        }
+        let mut component = vec![start];
+        reached.insert(start);
+        let mut index = 0;
+        while index < component.len() {
+            for neighbor in links.get(&component[index]).into_iter().flatten() {
+                if reached.insert(*neighbor) {
+                    component.push(*neighbor);
+                }
+            }
+            index += 1;
+        }
+
+        if !joined.is_empty() {
+            let a = *component.choose(rng).unwrap();
+            let b = *joined.choose(rng).unwrap();
+            link(links, a as usize, b as usize);
+        }
+        joined.extend(component);
+    }
+}
+
+fn label(id: NodeId, config: &Config) -> String {
+    if config.client.iter().any(|client| client.id == id) {
+        format!("c{}", id)
+    } else if config.server.iter().any(|server| server.id == id) {
+        format!("s{}", id)
+    } else {
+        format!("d{:02}", id)
+    }
+}
+
+fn description(options: &GeneratorOptions, config: &Config) -> String {
+    let mut description = String::new();
+    writeln!(description, "# {}", options.shape.name()).unwrap();
+    writeln!(description, "## Ascii representation").unwrap();
+    writeln!(description, "```").unwrap();
+    description.push_str(&diagram(options.shape, config));
+    writeln!(description, "```").unwrap();
+    writeln!(description, "## Description").unwrap();
+    description.push_str(&summary(options, config));
+    description
+}
+
+/// Drawn for the regular shapes, a list of links for the others
+fn diagram(shape: Shape, config: &Config) -> String {
+    let mut diagram = String::new();
+    let row = |ids: &mut dyn Iterator<Item = usize>| {
+        ids.map(|id| label(id as NodeId, config))
+            .collect::<Vec<String>>()
+            .join("----")
+    };
+    let rungs = |count: usize| format!(" {}", vec!["|"; count].join("      "));
+    match shape {
+        Shape::DoubleChain { length } => {
+            writeln!(diagram, "{}", row(&mut (0..length).map(|i| 2 * i + 1))).unwrap();
+            writeln!(diagram, "{}", rungs(length)).unwrap();
+            writeln!(diagram, "{}", row(&mut (0..length).map(|i| 2 * i + 2))).unwrap();
+        }
+        Shape::Grid { width, height } => {
+            for line in 0..height {
+                if line > 0 {
+                    writeln!(diagram, "{}", rungs(width)).unwrap();
+                }
+                writeln!(
+                    diagram,
+                    "{}",
+                    row(&mut (1..=width).map(|i| line * width + i))
+                )
+                .unwrap();
+            }
+        }
+        Shape::Ring { drones } => {
+            let line = row(&mut (1..=drones));
+            writeln!(diagram, "{}", line).unwrap();
+            writeln!(diagram, " |{}|", "_".repeat(line.len().saturating_sub(3))).unwrap();
+        }
+        Shape::Tree { .. } | Shape::Star { .. } => tree(&mut diagram, 1, "", config),
+        // one column per stage, the links are listed below
+        Shape::Butterfly { stages } => {
+            let rows = 1 << stages.saturating_sub(1);
+            for line in 0..rows {
+                let ids =
+                    (0..stages).map(|stage| label((stage * rows + line + 1) as NodeId, config));
+                writeln!(diagram, "{}", ids.collect::<Vec<String>>().join("    ")).unwrap();
+            }
+        }
+        Shape::Random { .. } => {}
+    }
+
+    if !diagram.is_empty() {
+        writeln!(diagram).unwrap();
+    }
+    for drone in config.drone.iter() {
+        let neighbors = drone
+            .connected_node_ids
+            .iter()
+            .map(|id| label(*id, config))
+            .collect::<Vec<String>>()
+            .join(", ");
+        writeln!(diagram, "{} -- {}", label(drone.id, config), neighbors).unwrap();
+    }
+    diagram
+}
+
+fn tree(diagram: &mut String, id: NodeId, prefix: &str, config: &Config) {
+    if id == 1 {
+        writeln!(diagram, "{}", label(id, config)).unwrap();
+    }
+    let children = config.drone[id as usize - 1]
+        .connected_node_ids
+        .iter()
+        .filter(|child| **child > id && config.drone.iter().any(|drone| drone.id == **child))
+        .copied()
+        .collect::<Vec<NodeId>>();
+    for (index, child) in children.iter().enumerate() {
+        let last = index + 1 == children.len();
+        let branch = if last { "└── " } else { "├── " };
+        writeln!(diagram, "{}{}{}", prefix, branch, label(*child, config)).unwrap();
+        let next_prefix = format!("{}{}", prefix, if last { "    " } else { "│   " });
+        tree(diagram, *child, &next_prefix, config);
+    }
+}
+
+fn summary(options: &GeneratorOptions, config: &Config) -> String {
+    let shape = match options.shape {
+        Shape::DoubleChain { length } => format!(
+            "{} Drones are connected in a double chain of {} drones per line.\n\
+             Each drone is connected to the next one and to the one in the same position in the other line.",
+            config.drone.len(),
+            length
+        ),
+        Shape::Star { .. } => format!(
+            "{} Drones are connected in a star: drone 1 is connected to every other drone.",
+            config.drone.len()
+        ),
+        Shape::Ring { .. } => format!(
+            "{} Drones are connected in a ring: each drone is connected to the next one and the last to the first.",
+            config.drone.len()
+        ),
+        Shape::Tree { depth, branching } => format!(
+            "{} Drones are connected in a tree of depth {}, each inner drone has {} children.",
+            config.drone.len(),
+            depth,
+            branching
+        ),
+        Shape::Grid { width, height } => format!(
+            "{} Drones are connected in a {}x{} grid, each drone to its horizontal and vertical neighbors.",
+            config.drone.len(),
+            width,
+            height
+        ),
+        Shape::Butterfly { stages } => format!(
+            "{} Drones are connected in a butterfly of {} stages, each drone to the same row and to the crossed row of the next stage.",
+            config.drone.len(),
+            stages
+        ),
+        Shape::Random { probability, .. } => format!(
+            "{} Drones are connected at random, each pair with probability {}, and the separate parts are then joined.",
+            config.drone.len(),
+            probability
+        ),
+    };
+    let placement = match options.placement {
+        Placement::Ends => "The clients are connected each to one of the first drones and the servers to the last two drones.",
+        Placement::Random => "The clients are connected to one or two random drones and the servers to two random drones.",
+    };
+    let pdr = match options.pdr {
+        PdrDistribution::Fixed(pdr) => format!("Every drone has a PDR of {}.", pdr),
+        PdrDistribution::Uniform(low, high) => {
+            format!(
+                "The PDRs are picked between {} and {} (seed {}).",
+                low, high, options.seed
+            )
+        }
+    };
+    format!("{}\n{}\n{}\n", shape, placement, pdr)
+}